@@ -0,0 +1,237 @@
+//! Allocation failure injection via a wrapping [`GlobalAlloc`].
+//!
+//! [`FaultyAlloc`] fails allocations when the [`ALLOC_FAILURE`] fault trips,
+//! so that code paths using `try_reserve` and other fallible allocation APIs
+//! can be exercised. Allocations are only failed within a [`scope`] on the
+//! current thread, unless the allocator is configured with
+//! [`unscoped`](FaultyAlloc::unscoped), and only when they request at least
+//! [`threshold`](FaultyAlloc::with_threshold) bytes.
+//!
+//! ```
+//! use std::alloc::System;
+//! use precept::alloc::FaultyAlloc;
+//!
+//! #[global_allocator]
+//! static ALLOC: FaultyAlloc<System> = FaultyAlloc::new(System).with_threshold(4096);
+//!
+//! let _scope = precept::alloc::scope();
+//! let mut buf: Vec<u8> = Vec::new();
+//! if buf.try_reserve(1 << 20).is_err() {
+//!     // handle the allocation failure
+//! }
+//! ```
+//!
+//! The allocator can't allocate or call into the dispatcher, so deciding
+//! whether to fail an allocation only consults the fault's enabled flag and
+//! pending trips, and draws randomness from a per-thread generator which
+//! [`scope`] seeds from the dispatcher. Random failures are not limited by
+//! the fault budget or recorded in the decision log. Instead the number of
+//! failures is reported as a `precept_fault` event when the scope ends.
+//!
+//! When the `enabled` feature is off [`FaultyAlloc`] forwards every call to
+//! the wrapped allocator.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    marker::PhantomData,
+};
+
+crate::declare_fault! {
+    /// Fails an allocation made through a [`FaultyAlloc`].
+    pub ALLOC_FAILURE = "alloc.failure";
+}
+
+thread_local! {
+    /// The number of open scopes on this thread.
+    static SCOPES: Cell<u32> = const { Cell::new(0) };
+
+    /// The number of allocations failed on this thread.
+    static FAILURES: Cell<u64> = const { Cell::new(0) };
+
+    /// The state of this thread's splitmix64 generator.
+    static RNG: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Returns the next value of this thread's generator.
+#[cfg(feature = "enabled")]
+fn next_random() -> u64 {
+    RNG.try_with(|state| {
+        let mut next = state.get();
+        let value = crate::random::splitmix64(&mut next);
+        state.set(next);
+        value
+    })
+    .unwrap_or(0)
+}
+
+/// A [`GlobalAlloc`] which fails allocations when [`ALLOC_FAILURE`] trips.
+///
+/// See the [module docs](self) for when allocations may fail.
+#[derive(Debug)]
+pub struct FaultyAlloc<A = System> {
+    inner: A,
+    threshold: usize,
+    scoped: bool,
+}
+
+impl<A> FaultyAlloc<A> {
+    /// Wraps `inner`, failing allocations of any size within a [`scope`].
+    pub const fn new(inner: A) -> Self {
+        Self { inner, threshold: 0, scoped: true }
+    }
+
+    /// Only fails allocations of at least `bytes` bytes.
+    pub const fn with_threshold(mut self, bytes: usize) -> Self {
+        self.threshold = bytes;
+        self
+    }
+
+    /// Fails allocations on every thread, not just within a [`scope`].
+    ///
+    /// Threads without an open scope draw randomness from an unseeded
+    /// generator, so their failures don't vary between runs.
+    pub const fn unscoped(mut self) -> Self {
+        self.scoped = false;
+        self
+    }
+
+    /// Returns a reference to the wrapped allocator.
+    pub const fn get_ref(&self) -> &A {
+        &self.inner
+    }
+
+    /// Returns true if an allocation of `size` bytes should fail.
+    #[cfg(feature = "enabled")]
+    #[inline]
+    fn should_fail(&self, size: usize) -> bool {
+        if size < self.threshold {
+            return false;
+        }
+        if self.scoped && SCOPES.try_with(Cell::get).unwrap_or(0) == 0 {
+            return false;
+        }
+        let fail =
+            ALLOC_FAILURE.take_pending() || (ALLOC_FAILURE.may_trip() && next_random() % 2 == 0);
+        if fail {
+            let _ = FAILURES.try_with(|failures| failures.set(failures.get() + 1));
+        }
+        fail
+    }
+
+    #[cfg(not(feature = "enabled"))]
+    #[inline(always)]
+    fn should_fail(&self, _size: usize) -> bool {
+        false
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for FaultyAlloc<A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if self.should_fail(layout.size()) {
+            return std::ptr::null_mut();
+        }
+        unsafe { self.inner.alloc(layout) }
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if self.should_fail(layout.size()) {
+            return std::ptr::null_mut();
+        }
+        unsafe { self.inner.alloc_zeroed(layout) }
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.inner.dealloc(ptr, layout) }
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if new_size > layout.size() && self.should_fail(new_size) {
+            return std::ptr::null_mut();
+        }
+        unsafe { self.inner.realloc(ptr, layout, new_size) }
+    }
+}
+
+/// Opts the current thread into allocation failures until the returned guard
+/// is dropped. Scopes may be nested.
+///
+/// Opening a scope seeds the thread's generator from the [`ALLOC_FAILURE`]
+/// substream of the dispatcher.
+pub fn scope() -> AllocScope {
+    RNG.with(|state| state.set(crate::dispatch::get_random_for(ALLOC_FAILURE.name())));
+    SCOPES.with(|scopes| scopes.set(scopes.get() + 1));
+    AllocScope {
+        failures: FAILURES.with(Cell::get),
+        _not_send: PhantomData,
+    }
+}
+
+/// Returns the number of allocations failed on the current thread.
+pub fn failures() -> u64 {
+    FAILURES.with(Cell::get)
+}
+
+/// Guard returned by [`scope`].
+#[derive(Debug)]
+#[must_use = "allocations can only fail while the scope is held"]
+pub struct AllocScope {
+    /// The number of failures on this thread when the scope was opened.
+    failures: u64,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for AllocScope {
+    fn drop(&mut self) {
+        SCOPES.with(|scopes| scopes.set(scopes.get() - 1));
+        let failures = failures() - self.failures;
+        crate::expect_sometimes!(failures > 0, ALLOC_FAILURE.property());
+        if failures > 0 {
+            crate::emit_event!("precept_fault", {
+                "name": ALLOC_FAILURE.name(),
+                "details": { "failures": failures },
+            });
+        }
+    }
+}
+
+#[cfg(all(test, feature = "enabled"))]
+mod tests {
+    use std::alloc::{GlobalAlloc, Layout, System};
+
+    use super::{ALLOC_FAILURE, FaultyAlloc, failures, scope};
+
+    #[test]
+    fn fails_only_in_scope_above_threshold() {
+        let _lock = crate::fault::lock_catalog();
+        let _quiet = ALLOC_FAILURE.disable_scoped();
+        let alloc = FaultyAlloc::new(System).with_threshold(64);
+        let small = Layout::from_size_align(8, 8).unwrap();
+        let large = Layout::from_size_align(128, 8).unwrap();
+
+        let _forced = ALLOC_FAILURE.force_scoped(1);
+        unsafe {
+            // outside of a scope the pending trip is left alone
+            let ptr = alloc.alloc(large);
+            assert!(!ptr.is_null());
+            alloc.dealloc(ptr, large);
+
+            let _scope = scope();
+            let ptr = alloc.alloc(small);
+            assert!(!ptr.is_null());
+            alloc.dealloc(ptr, small);
+
+            let before = failures();
+            assert!(alloc.alloc(large).is_null());
+            assert_eq!(failures(), before + 1);
+
+            let ptr = alloc.alloc(large);
+            assert!(!ptr.is_null());
+            alloc.dealloc(ptr, large);
+        }
+    }
+}
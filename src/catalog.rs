@@ -2,7 +2,7 @@ use std::{
     panic::Location,
     sync::{
         LazyLock,
-        atomic::{self, AtomicUsize},
+        atomic::{self, AtomicBool, AtomicUsize},
     },
 };
 
@@ -90,10 +90,25 @@ impl CatalogEntry {
         };
         // only emit on the first pass or fail
         if count == 0 {
+            let details = if !condition && self.is_first_always_failure() {
+                crate::fault::attach_recent_decisions(details)
+            } else {
+                details
+            };
             dispatch::emit(Event::EmitEntry { entry: self, condition, details });
         }
     }
 
+    /// Returns true the first time any `Always` expectation fails, which is
+    /// when the fault decision log is attached to its details.
+    fn is_first_always_failure(&self) -> bool {
+        static ALWAYS_FAILED: AtomicBool = AtomicBool::new(false);
+        matches!(
+            self.expectation,
+            Expectation::Always | Expectation::AlwaysOrUnreachable
+        ) && !ALWAYS_FAILED.swap(true, atomic::Ordering::AcqRel)
+    }
+
     #[inline]
     pub fn expectation(&self) -> Expectation {
         self.expectation
@@ -0,0 +1,120 @@
+//! Hash collections whose iteration order is drawn from the dispatcher.
+//!
+//! The standard library seeds each [`HashMap`](std::collections::HashMap)
+//! from OS entropy, so iteration order differs between runs even when the
+//! dispatcher is seeded. [`HashMap`] and [`HashSet`] instead use a
+//! [`RandomState`] seeded from the dispatcher, so whole-program replay stays
+//! deterministic.
+//!
+//! As with other custom hashers, construct the collections with
+//! [`Default::default`] rather than `new`:
+//!
+//! ```
+//! use precept::collections::HashMap;
+//!
+//! let mut replicas: HashMap<&str, u64> = HashMap::default();
+//! replicas.insert("a", 1);
+//! ```
+//!
+//! When the `enabled` feature is off [`RandomState`] delegates to
+//! [`std::hash::RandomState`].
+
+use std::hash::{BuildHasher, DefaultHasher};
+
+#[cfg(feature = "enabled")]
+use std::hash::Hasher;
+
+/// A [`std::collections::HashMap`] using a dispatcher-seeded [`RandomState`].
+pub type HashMap<K, V, S = RandomState> = std::collections::HashMap<K, V, S>;
+
+/// A [`std::collections::HashSet`] using a dispatcher-seeded [`RandomState`].
+pub type HashSet<T, S = RandomState> = std::collections::HashSet<T, S>;
+
+/// Labels the substream [`RandomState`] seeds are drawn from.
+#[cfg(feature = "enabled")]
+const HASHER_LABEL: &str = "collections.hasher";
+
+/// A [`BuildHasher`] seeded from the dispatcher, mirroring
+/// [`std::hash::RandomState`].
+#[derive(Debug, Clone)]
+pub struct RandomState {
+    #[cfg(feature = "enabled")]
+    seed: u64,
+    #[cfg(not(feature = "enabled"))]
+    inner: std::hash::RandomState,
+}
+
+impl RandomState {
+    /// Creates a new `RandomState` with a seed drawn from the dispatcher's
+    /// `collections.hasher` substream.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "enabled")]
+            seed: crate::dispatch::get_random_for(HASHER_LABEL),
+            #[cfg(not(feature = "enabled"))]
+            inner: std::hash::RandomState::new(),
+        }
+    }
+}
+
+impl Default for RandomState {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildHasher for RandomState {
+    type Hasher = DefaultHasher;
+
+    #[inline]
+    fn build_hasher(&self) -> DefaultHasher {
+        #[cfg(feature = "enabled")]
+        {
+            let mut hasher = DefaultHasher::new();
+            hasher.write_u64(self.seed);
+            hasher
+        }
+        #[cfg(not(feature = "enabled"))]
+        self.inner.build_hasher()
+    }
+}
+
+#[cfg(all(test, feature = "enabled"))]
+mod tests {
+    use std::hash::BuildHasher;
+
+    use super::{HashMap, HashSet, RandomState};
+
+    #[test]
+    fn seeded_hashers_are_deterministic() {
+        let (a, b) = (RandomState { seed: 7 }, RandomState { seed: 7 });
+        assert_eq!(a.hash_one("key"), b.hash_one("key"));
+        assert_ne!(a.hash_one("key"), RandomState { seed: 8 }.hash_one("key"));
+
+        let mut map: HashMap<_, _> = HashMap::default();
+        map.insert("key", 1);
+        let set: HashSet<_> = map.keys().copied().collect();
+        assert!(set.contains("key"));
+    }
+
+    #[test]
+    fn new_draws_its_seed_from_a_substream() {
+        use crate::dispatch::{
+            Dispatch, dispatch_scoped, get_random, noop::NoopDispatch, seeded::SeededDispatch,
+        };
+
+        static NOOP: NoopDispatch = NoopDispatch;
+        let seeded = || {
+            let _scoped = dispatch_scoped(Box::leak(Box::new(SeededDispatch::new(7, &NOOP))));
+            (RandomState::new().hash_one("key"), get_random())
+        };
+        // the seed leaves the main stream untouched
+        let expected = SeededDispatch::new(7, &NOOP);
+        let (seed, next) = (expected.random_for(super::HASHER_LABEL), expected.random());
+
+        assert_eq!(seeded(), (RandomState { seed }.hash_one("key"), next));
+        assert_eq!(seeded(), seeded());
+    }
+}
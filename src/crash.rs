@@ -0,0 +1,288 @@
+//! Crash-point injection and a restart-and-recover harness for
+//! crash-consistency testing.
+//!
+//! A crash point, registered with [`crash_point!`](crate::crash_point), is a
+//! fault which terminates the process when it trips. Crash points are disabled
+//! by default so that they only fire when explicitly armed, either by a test
+//! via [`FaultEntry::set_pending`] or by a [`CrashHarness`].
+//! [`enable_all`](crate::fault::enable_all) and
+//! [`swarm`](crate::fault::swarm) leave them disabled.
+//!
+//! The harness runs a program twice: first with a single crash point armed,
+//! then again in recovery mode so that the program can check its recovery
+//! properties (for example with [`expect_always!`](crate::expect_always)) and
+//! exit successfully if they hold.
+//!
+//! ```no_run
+//! use precept::crash::CrashHarness;
+//!
+//! let harness = CrashHarness::current_exe().unwrap().arg("--storage-test");
+//! for report in harness.run_all().unwrap() {
+//!     assert!(report.recovered(), "failed to recover from {report:?}");
+//! }
+//! ```
+
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    io,
+    path::PathBuf,
+    process::{Command, ExitStatus},
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+use crate::fault::FaultEntry;
+
+/// The environment variable naming the crash point which [`crate::init`]
+/// arms in a harness child process.
+pub const CRASH_POINT_ENV: &str = "PRECEPT_CRASH_POINT";
+
+/// The environment variable naming the crash point a harness child process
+/// is recovering from.
+pub const RECOVERY_ENV: &str = "PRECEPT_RECOVERY";
+
+/// Every crash point registered by [`crash_point!`](crate::crash_point). Crash
+/// points are also registered in the fault catalog.
+#[cfg(feature = "enabled")]
+#[doc(hidden)]
+#[linkme::distributed_slice]
+pub static CRASH_POINTS: [&'static FaultEntry];
+
+#[cfg(not(feature = "enabled"))]
+#[doc(hidden)]
+pub static CRASH_POINTS: [&&FaultEntry; 0] = [];
+
+/// Returns every registered crash point.
+pub fn crash_points() -> impl Iterator<Item = &'static FaultEntry> {
+    CRASH_POINTS.into_iter().copied()
+}
+
+/// Returns true if `fault` is a crash point.
+pub(crate) fn is_crash_point(fault: &FaultEntry) -> bool {
+    crash_points().any(|point| std::ptr::eq(point, fault))
+}
+
+/// Arms the crash point named by [`CRASH_POINT_ENV`], if any.
+pub(crate) fn init_crash_point() {
+    if let Ok(name) = env::var(CRASH_POINT_ENV) {
+        match crash_points().find(|point| point.name() == name) {
+            Some(point) => point.set_pending(1),
+            None => tracing::warn!(name, "unknown Precept crash point"),
+        }
+    }
+}
+
+/// Returns the name of the crash point this process is recovering from, if
+/// it was started by a [`CrashHarness`] in recovery mode.
+pub fn recovering_from() -> Option<String> {
+    env::var(RECOVERY_ENV).ok()
+}
+
+/// Returns true if this process was started by a [`CrashHarness`] in recovery
+/// mode.
+pub fn is_recovering() -> bool {
+    env::var_os(RECOVERY_ENV).is_some()
+}
+
+/// How the process terminates when a crash point trips.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrashAction {
+    /// Terminate via [`std::process::abort`]. This is the default.
+    Abort,
+    /// Terminate via [`std::process::exit`] with the given code.
+    Exit(i32),
+}
+
+// i32::MIN encodes CrashAction::Abort
+static CRASH_ACTION: AtomicI32 = AtomicI32::new(i32::MIN);
+
+/// Sets how the process terminates when a crash point trips.
+pub fn set_action(action: CrashAction) {
+    let encoded = match action {
+        CrashAction::Abort => i32::MIN,
+        CrashAction::Exit(code) => code,
+    };
+    CRASH_ACTION.store(encoded, Ordering::Release);
+}
+
+#[doc(hidden)]
+pub fn crash(point: &FaultEntry) -> ! {
+    tracing::error!(name = point.name(), "Precept crash point tripped");
+    match CRASH_ACTION.load(Ordering::Acquire) {
+        i32::MIN => std::process::abort(),
+        code => std::process::exit(code),
+    }
+}
+
+/// Runs a program with a single crash point armed and then restarts it in
+/// recovery mode.
+///
+/// The program must call [`crate::init`] so that the crash point named by
+/// [`CRASH_POINT_ENV`] is armed, and should check its recovery properties when
+/// [`is_recovering`] returns true.
+#[derive(Debug, Clone)]
+pub struct CrashHarness {
+    program: PathBuf,
+    args: Vec<OsString>,
+    envs: Vec<(OsString, OsString)>,
+}
+
+impl CrashHarness {
+    /// Creates a harness which runs `program`.
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            args: vec![],
+            envs: vec![],
+        }
+    }
+
+    /// Creates a harness which runs the current executable.
+    pub fn current_exe() -> io::Result<Self> {
+        Ok(Self::new(env::current_exe()?))
+    }
+
+    /// Adds an argument passed to both the crashing and recovering runs.
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Adds arguments passed to both the crashing and recovering runs.
+    pub fn args<I: IntoIterator<Item = S>, S: AsRef<OsStr>>(mut self, args: I) -> Self {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self
+    }
+
+    /// Sets an environment variable for both the crashing and recovering runs.
+    pub fn env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.envs
+            .push((key.as_ref().to_owned(), value.as_ref().to_owned()));
+        self
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .envs(self.envs.iter().map(|(k, v)| (k, v)));
+        command.env_remove(CRASH_POINT_ENV).env_remove(RECOVERY_ENV);
+        command
+    }
+
+    /// Runs the program with `crash_point` armed, then restarts it in recovery
+    /// mode.
+    ///
+    /// The result is reported as a `precept_crash_harness` event.
+    pub fn run(&self, crash_point: &str) -> io::Result<CrashReport> {
+        let crash_status = self.command().env(CRASH_POINT_ENV, crash_point).status()?;
+        let recovery_status = self.command().env(RECOVERY_ENV, crash_point).status()?;
+        let report = CrashReport {
+            crash_point: crash_point.to_owned(),
+            crash_status,
+            recovery_status,
+        };
+        crate::dispatch::emit(crate::dispatch::Event::Custom {
+            name: "precept_crash_harness",
+            value: serde_json::json!({
+                "crash_point": report.crash_point,
+                "crashed": report.crashed(),
+                "recovered": report.recovered(),
+            }),
+        });
+        Ok(report)
+    }
+
+    /// Runs [`run`](Self::run) for every crash point registered in the current
+    /// executable.
+    pub fn run_all(&self) -> io::Result<Vec<CrashReport>> {
+        crash_points().map(|point| self.run(point.name())).collect()
+    }
+}
+
+/// The outcome of a single [`CrashHarness::run`].
+#[derive(Debug, Clone)]
+pub struct CrashReport {
+    crash_point: String,
+    crash_status: ExitStatus,
+    recovery_status: ExitStatus,
+}
+
+impl CrashReport {
+    /// The crash point which was armed.
+    pub fn crash_point(&self) -> &str {
+        &self.crash_point
+    }
+
+    /// The exit status of the crashing run.
+    pub fn crash_status(&self) -> ExitStatus {
+        self.crash_status
+    }
+
+    /// The exit status of the recovering run.
+    pub fn recovery_status(&self) -> ExitStatus {
+        self.recovery_status
+    }
+
+    /// Returns true if the crashing run terminated unsuccessfully, which
+    /// usually means that the crash point was reached.
+    pub fn crashed(&self) -> bool {
+        !self.crash_status.success()
+    }
+
+    /// Returns true if the recovering run exited successfully.
+    pub fn recovered(&self) -> bool {
+        self.recovery_status.success()
+    }
+}
+
+#[cfg(all(test, feature = "enabled"))]
+mod tests {
+    use super::{CrashHarness, is_recovering, recovering_from};
+
+    const CHILD_ENV: &str = "PRECEPT_TEST_CRASH_CHILD";
+
+    // Only does anything when run as a child of `harness_crashes_and_recovers`.
+    #[test]
+    fn crash_harness_child() {
+        if std::env::var_os(CHILD_ENV).is_none() {
+            return;
+        }
+        crate::fault::init_faults();
+        super::init_crash_point();
+        if is_recovering() {
+            assert_eq!(recovering_from().as_deref(), Some("test_crash_point"));
+            return;
+        }
+        crate::crash_point!("test_crash_point");
+        panic!("crash point did not fire");
+    }
+
+    #[test]
+    fn harness_crashes_and_recovers() {
+        assert!(super::crash_points().any(|point| point.name() == "test_crash_point"));
+
+        let report = CrashHarness::current_exe()
+            .unwrap()
+            .args(["crash::tests::crash_harness_child", "--exact", "--quiet"])
+            .env(CHILD_ENV, "1")
+            .run("test_crash_point")
+            .unwrap();
+        assert_eq!(report.crash_point(), "test_crash_point");
+        assert!(report.crashed(), "{report:?}");
+        assert!(report.recovered(), "{report:?}");
+    }
+
+    #[test]
+    fn enable_all_skips_crash_points() {
+        let _lock = crate::fault::lock_catalog();
+        let _restore = crate::fault::only_enable(&[]);
+        let _setup = crate::dispatch::setup_scoped(true);
+        crate::fault::enable_all();
+        for _ in 0..100 {
+            crate::crash_point!("enable_all_crash_point");
+        }
+        assert!(super::crash_points().all(|point| !point.is_enabled()));
+    }
+}
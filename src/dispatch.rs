@@ -1,10 +1,14 @@
-use std::sync::OnceLock;
+use std::sync::{
+    OnceLock,
+    atomic::{AtomicBool, Ordering},
+};
 
 use noop::NoopDispatch;
 
 use crate::catalog::CatalogEntry;
 
 pub mod noop;
+pub mod seeded;
 pub mod test;
 
 #[cfg(feature = "antithesis")]
@@ -46,6 +50,20 @@ pub trait Dispatch: Sync + Send {
     fn emit(&self, event: Event);
     /// Returns a random u64 value for decision making.
     fn random(&self) -> u64;
+
+    /// Returns the next random u64 value of the independent substream
+    /// labeled `label`.
+    ///
+    /// By default every substream is derived from a single process-wide seed,
+    /// drawn from the dispatcher passed to [`init`](crate::init) before
+    /// anything else runs, so draws from one substream don't shift the values
+    /// of another. Dispatchers which guide exploration through the values
+    /// they return should override this to keep control of every decision,
+    /// and dispatchers with a seed of their own should derive their
+    /// substreams from it.
+    fn random_for(&self, label: &str) -> u64 {
+        crate::random::next_in_substream(label, || self.random())
+    }
 }
 
 static DISPATCHER: OnceLock<&'static dyn Dispatch> = OnceLock::new();
@@ -72,6 +90,10 @@ pub fn set_dispatcher(dispatcher: &'static dyn Dispatch) -> Result<(), SetDispat
 ///
 /// If a dispatcher has not been set, a no-op implementation is returned.
 pub fn dispatcher() -> &'static dyn Dispatch {
+    #[cfg(all(test, feature = "enabled"))]
+    if let Some(dispatch) = SCOPED_DISPATCHER.get() {
+        return dispatch;
+    }
     match DISPATCHER.get() {
         Some(dispatch) => *dispatch,
         None => {
@@ -81,22 +103,79 @@ pub fn dispatcher() -> &'static dyn Dispatch {
     }
 }
 
+#[cfg(all(test, feature = "enabled"))]
+thread_local! {
+    static SCOPED_DISPATCHER: std::cell::Cell<Option<&'static dyn Dispatch>> =
+        const { std::cell::Cell::new(None) };
+}
+
+/// Uses `dispatcher` on the current thread until the returned guard is
+/// dropped.
+#[cfg(all(test, feature = "enabled"))]
+pub(crate) fn dispatch_scoped(dispatcher: &'static dyn Dispatch) -> DispatchGuard {
+    DispatchGuard(SCOPED_DISPATCHER.replace(Some(dispatcher)))
+}
+
+/// Restores the previous dispatcher of the current thread when dropped.
+#[cfg(all(test, feature = "enabled"))]
+pub(crate) struct DispatchGuard(Option<&'static dyn Dispatch>);
+
+#[cfg(all(test, feature = "enabled"))]
+impl Drop for DispatchGuard {
+    fn drop(&mut self) {
+        SCOPED_DISPATCHER.set(self.0);
+    }
+}
+
 /// Generate a random u64 using the dispatcher
 pub fn get_random() -> u64 {
     dispatcher().random()
 }
 
+/// Generate a random u64 from the substream labeled `label` using the
+/// dispatcher
+pub fn get_random_for(label: &str) -> u64 {
+    dispatcher().random_for(label)
+}
+
 /// Choose a random value from a slice of options using the dispatcher
 pub fn choose<T>(options: &[T]) -> Option<&T> {
     if options.is_empty() {
         None
     } else {
-        let idx: usize = (get_random() as usize) % options.len();
-        Some(&options[idx])
+        let idx = crate::random::below_from(options.len() as u64, get_random);
+        Some(&options[idx as usize])
     }
 }
 
+static SETUP_COMPLETE: AtomicBool = AtomicBool::new(false);
+
+/// Returns true once a [`Event::SetupComplete`] event has been emitted.
+pub fn is_setup_complete() -> bool {
+    SETUP_COMPLETE.load(Ordering::Acquire)
+}
+
 /// Emit an event using the dispatcher
 pub fn emit(event: Event) {
+    if let Event::SetupComplete { .. } = event {
+        SETUP_COMPLETE.store(true, Ordering::Release);
+    }
     dispatcher().emit(event);
 }
+
+/// Sets whether setup is complete until the returned guard is dropped.
+#[cfg(test)]
+pub(crate) fn setup_scoped(complete: bool) -> SetupGuard {
+    SetupGuard(SETUP_COMPLETE.swap(complete, Ordering::AcqRel))
+}
+
+/// Restores the previous setup state when dropped.
+#[cfg(test)]
+pub(crate) struct SetupGuard(bool);
+
+#[cfg(test)]
+impl Drop for SetupGuard {
+    fn drop(&mut self) {
+        SETUP_COMPLETE.store(self.0, Ordering::Release);
+    }
+}
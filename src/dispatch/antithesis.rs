@@ -79,6 +79,12 @@ impl Dispatch for AntithesisDispatch {
             Self::File(handler) => handler.random(),
         }
     }
+
+    fn random_for(&self, _label: &str) -> u64 {
+        // Antithesis guides exploration through every random value, so
+        // substreams draw straight from it
+        self.random()
+    }
 }
 
 #[derive(Serialize)]
@@ -0,0 +1,104 @@
+use std::sync::{Mutex, PoisonError};
+
+use super::{Dispatch, Event};
+use crate::random::Substreams;
+
+/// A dispatcher which draws randomness from a seeded generator and forwards
+/// events to another dispatcher.
+///
+/// A single-threaded program which draws all of its nondeterminism from the
+/// dispatcher, such as a [`Sim`](crate::sim::Sim), replays exactly when run
+/// again with the same seed.
+///
+/// Labeled draws, which decide whether each fault trips, come from substreams
+/// derived from the same seed, so extra unlabeled draws don't change them.
+pub struct SeededDispatch {
+    inner: &'static dyn Dispatch,
+    /// The state of a splitmix64 generator.
+    state: Mutex<u64>,
+    substreams: Mutex<Substreams>,
+}
+
+impl SeededDispatch {
+    /// Creates a dispatcher seeded with `seed` which forwards events to
+    /// `inner`.
+    pub fn new(seed: u64, inner: &'static dyn Dispatch) -> Self {
+        Self {
+            inner,
+            state: Mutex::new(seed),
+            substreams: Mutex::new(Substreams::new(seed)),
+        }
+    }
+
+    /// Like [`new`](Self::new), but boxed for use with
+    /// [`init_boxed`](crate::init_boxed).
+    pub fn new_boxed(seed: u64, inner: &'static dyn Dispatch) -> Box<dyn Dispatch> {
+        Box::new(Self::new(seed, inner))
+    }
+}
+
+impl Dispatch for SeededDispatch {
+    fn emit(&self, event: Event) {
+        self.inner.emit(event);
+    }
+
+    fn random(&self) -> u64 {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        crate::random::splitmix64(&mut state)
+    }
+
+    fn random_for(&self, label: &str) -> u64 {
+        let mut substreams = self
+            .substreams
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        substreams.next(label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SeededDispatch;
+    use crate::dispatch::{Dispatch, noop::NoopDispatch};
+
+    static NOOP: NoopDispatch = NoopDispatch;
+
+    #[test]
+    fn same_seed_replays_draws() {
+        let (first, second) = (SeededDispatch::new(7, &NOOP), SeededDispatch::new(7, &NOOP));
+        let draws = |dispatch: &SeededDispatch| {
+            (0..8)
+                .map(|_| (dispatch.random(), dispatch.random_for("a")))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(draws(&first), draws(&second));
+        assert_ne!(draws(&first), draws(&SeededDispatch::new(8, &NOOP)));
+    }
+
+    #[test]
+    fn substreams_ignore_unlabeled_draws() {
+        let (first, second) = (SeededDispatch::new(7, &NOOP), SeededDispatch::new(7, &NOOP));
+        second.random();
+        second.random_for("b");
+        assert_eq!(first.random_for("a"), second.random_for("a"));
+    }
+
+    #[cfg(feature = "enabled")]
+    #[test]
+    fn extra_draws_do_not_shift_fault_trips() {
+        use crate::{dispatch::dispatch_scoped, fault::FaultEntry};
+
+        static FAULT: FaultEntry = FaultEntry::new("seeded_extra_draws");
+        FAULT.set_requires_setup(false);
+        let _lock = crate::fault::lock_catalog();
+        let trips = |extra_draw: bool| {
+            let dispatch = Box::leak(Box::new(SeededDispatch::new(42, &NOOP)));
+            let _scoped = dispatch_scoped(dispatch);
+            if extra_draw {
+                crate::dispatch::get_random();
+            }
+            (0..64).map(|_| FAULT.trip()).collect::<Vec<_>>()
+        };
+        assert_eq!(trips(false), trips(true));
+    }
+}
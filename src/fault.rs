@@ -1,10 +1,24 @@
 use std::{
     collections::HashSet,
     fmt::Debug,
-    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    panic::Location,
+    sync::{
+        LazyLock, Mutex, PoisonError,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
-use crate::ENABLED;
+use crate::{
+    ENABLED,
+    catalog::{CatalogEntry, Expectation},
+    dispatch::Event,
+};
+
+mod decision;
+
+pub(crate) use decision::attach_recent_decisions;
+pub use decision::{DECISION_LOG_CAPACITY, FaultDecision, TripKind, recent_decisions};
 
 #[cfg(feature = "enabled")]
 #[doc(hidden)]
@@ -34,12 +48,24 @@ pub struct FaultEntry {
     /// the name of the fault, also serves as its Catalog id
     name: &'static str,
 
+    /// the property of the `Sometimes` expectation tracking whether this fault
+    /// has tripped
+    property: &'static str,
+
     /// whether or not this fault is enabled
     enabled: AtomicBool,
 
+    /// whether this fault is prevented from randomly tripping until setup is
+    /// complete
+    requires_setup: AtomicBool,
+
     /// if this value is > 0, the next call to `trip` will return true and this
     /// value will be decremented
     pending_trips: AtomicU32,
+
+    /// the `Sometimes` catalog entry of a fault registered at runtime, which
+    /// `trip` reports to itself since no fault macro does it
+    entry: Option<&'static CatalogEntry>,
 }
 
 impl FaultEntry {
@@ -47,30 +73,120 @@ impl FaultEntry {
     pub const fn new(name: &'static str) -> Self {
         Self {
             name,
+            property: name,
             enabled: AtomicBool::new(true),
+            requires_setup: AtomicBool::new(true),
             pending_trips: AtomicU32::new(0),
+            entry: None,
         }
     }
 
+    /// Sets whether this fault starts out enabled. Faults are enabled by
+    /// default.
+    pub const fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = AtomicBool::new(enabled);
+        self
+    }
+
+    #[doc(hidden)]
+    pub const fn with_property(mut self, property: &'static str) -> Self {
+        self.property = property;
+        self
+    }
+
+    /// Returns the name of this fault.
+    #[inline]
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub const fn property(&self) -> &'static str {
+        self.property
+    }
+
     /// Returns true when the fault should trip
+    ///
+    /// Unless configured otherwise via
+    /// [`set_requires_setup`](Self::set_requires_setup), a fault only trips
+    /// randomly once setup has been signaled with
+    /// [`setup_complete!`](crate::setup_complete). Random trips also consume
+    /// the global fault budget (see [`set_budget`]). Pending forced trips are
+    /// honored regardless.
+    ///
+    /// Each trip is recorded in the decision log (see [`recent_decisions`]).
+    #[track_caller]
     pub fn trip(&self) -> bool {
-        if self
-            .pending_trips
-            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
-                if count > 0 { Some(count - 1) } else { None }
-            })
-            .is_ok()
-        {
+        let tripped = if self.take_pending() {
             // forced trigger
+            decision::record(self, Location::caller(), TripKind::Forced);
+            true
+        } else if self.may_trip() && random_trip(self.name) && consume_budget(self) {
+            decision::record(self, Location::caller(), TripKind::Random);
             true
-        } else if self.enabled.load(Ordering::Acquire) {
-            let should_fault = crate::dispatch::choose(&[true, false]);
-            should_fault.is_some_and(|&t| t)
         } else {
             false
+        };
+        self.report(tripped);
+        tripped
+    }
+
+    /// Reports whether a fault registered at runtime tripped to its catalog
+    /// entry.
+    fn report(&self, tripped: bool) {
+        if let Some(entry) = self.entry {
+            entry.emit(tripped, serde_json::json!({ "name": self.name }));
         }
     }
 
+    /// Returns true if this fault is allowed to randomly trip right now.
+    pub(crate) fn may_trip(&self) -> bool {
+        self.is_enabled() && (!self.requires_setup() || crate::dispatch::is_setup_complete())
+    }
+
+    /// Consumes one pending forced trip, returning false if there are none.
+    pub(crate) fn take_pending(&self) -> bool {
+        self.pending_trips
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                if count > 0 { Some(count - 1) } else { None }
+            })
+            .is_ok()
+    }
+
+    /// Returns a delay drawn from `distribution` when the fault should trip.
+    ///
+    /// Used by latency faults, which share the enable and pending trip
+    /// machinery of every other fault.
+    #[track_caller]
+    pub fn trip_latency(
+        &self,
+        max: Duration,
+        distribution: LatencyDistribution,
+    ) -> Option<Duration> {
+        self.trip()
+            .then(|| distribution.sample_from(max, || crate::dispatch::get_random_for(self.name)))
+    }
+
+    /// Returns true if this fault is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    /// Returns true if this fault is prevented from randomly tripping until
+    /// setup is complete.
+    pub fn requires_setup(&self) -> bool {
+        self.requires_setup.load(Ordering::Acquire)
+    }
+
+    /// Sets whether this fault is prevented from randomly tripping until setup
+    /// is complete. Faults require setup by default.
+    ///
+    /// Clear this for faults which should be exercised during bootstrap.
+    pub fn set_requires_setup(&self, requires_setup: bool) {
+        self.requires_setup.store(requires_setup, Ordering::Release);
+    }
+
     /// Enables this fault, allowing it to trip.
     pub fn enable(&self) {
         self.enabled.store(true, Ordering::Release);
@@ -93,14 +209,252 @@ impl FaultEntry {
     pub fn count_pending(&self) -> u32 {
         self.pending_trips.load(Ordering::Acquire)
     }
+
+    /// Forces the next `count` calls to [`trip`](Self::trip) to return `true`
+    /// until the returned guard is dropped.
+    ///
+    /// Dropping the guard (including while unwinding from a panic) restores
+    /// this fault's previous enabled flag and pending trip count.
+    #[must_use = "the fault is restored as soon as the guard is dropped"]
+    pub fn force_scoped(&self, count: u32) -> FaultGuard<'_> {
+        let guard = FaultGuard::save([self]);
+        self.set_pending(count);
+        guard
+    }
+
+    /// Enables this fault until the returned guard is dropped.
+    #[must_use = "the fault is restored as soon as the guard is dropped"]
+    pub fn enable_scoped(&self) -> FaultGuard<'_> {
+        let guard = FaultGuard::save([self]);
+        self.enable();
+        guard
+    }
+
+    /// Disables this fault until the returned guard is dropped.
+    #[must_use = "the fault is restored as soon as the guard is dropped"]
+    pub fn disable_scoped(&self) -> FaultGuard<'_> {
+        let guard = FaultGuard::save([self]);
+        self.disable();
+        guard
+    }
+
+    fn snapshot(&self) -> FaultSnapshot {
+        FaultSnapshot {
+            enabled: self.enabled.load(Ordering::Acquire),
+            pending_trips: self.pending_trips.load(Ordering::Acquire),
+        }
+    }
+
+    fn restore(&self, snapshot: &FaultSnapshot) {
+        self.enabled.store(snapshot.enabled, Ordering::Release);
+        self.pending_trips
+            .store(snapshot.pending_trips, Ordering::Release);
+    }
+}
+
+/// Flips a coin using the substream labeled `label`.
+fn random_trip(label: &str) -> bool {
+    crate::dispatch::get_random_for(label) % 2 == 0
+}
+
+/// Selects at most one of several alternative faults to trip.
+///
+/// Each alternative is paired with a relative weight. An alternative with
+/// pending forced trips is always selected first. Otherwise, the choice trips
+/// 50% of the time, selecting one of the alternatives which may trip (see
+/// [`FaultEntry::trip`]) with a non-zero weight in proportion to its weight.
+///
+/// This backs [`choose_fault!`](crate::choose_fault), which registers one fault
+/// per alternative so that each can be disabled or forced individually.
+#[track_caller]
+pub fn trip_choice(alternatives: &[(&'static FaultEntry, u32)]) -> Option<&'static FaultEntry> {
+    if let Some(&(fault, _)) = alternatives.iter().find(|(fault, _)| fault.take_pending()) {
+        // forced trigger
+        decision::record(fault, Location::caller(), TripKind::Forced);
+        fault.report(true);
+        return Some(fault);
+    }
+
+    let enabled = alternatives
+        .iter()
+        .filter(|(fault, weight)| *weight > 0 && fault.may_trip());
+    let total: u64 = enabled.clone().map(|&(_, weight)| u64::from(weight)).sum();
+    if total == 0 {
+        return None;
+    }
+    // the choice draws from the substream of the first alternative
+    let label = alternatives[0].0.name;
+    if !random_trip(label) {
+        return None;
+    }
+
+    let mut pick = crate::random::below_from(total, || crate::dispatch::get_random_for(label));
+    for &(fault, weight) in enabled {
+        match pick.checked_sub(u64::from(weight)) {
+            Some(rest) => pick = rest,
+            None if consume_budget(fault) => {
+                decision::record(fault, Location::caller(), TripKind::Random);
+                fault.report(true);
+                return Some(fault);
+            }
+            None => return None,
+        }
+    }
+    unreachable!("pick is always less than the total weight")
+}
+
+/// The distribution a latency fault draws its delay from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LatencyDistribution {
+    /// Any delay between zero and the maximum is equally likely.
+    #[default]
+    Uniform,
+    /// Mostly short delays with a long tail, averaging a quarter of the
+    /// maximum.
+    Exponential,
+    /// Usually a very short delay, but one in ten delays is at least half of
+    /// the maximum.
+    Spike,
+}
+
+impl LatencyDistribution {
+    /// Draws a delay no longer than `max` using the dispatcher.
+    pub fn sample(self, max: Duration) -> Duration {
+        self.sample_from(max, crate::dispatch::get_random)
+    }
+
+    /// Draws a delay no longer than `max` using `random`.
+    fn sample_from(self, max: Duration, random: impl Fn() -> u64) -> Duration {
+        let max_nanos = u64::try_from(max.as_nanos()).unwrap_or(u64::MAX);
+        let uniform = |max: u64| match max.checked_add(1) {
+            Some(span) => crate::random::below_from(span, &random),
+            None => random(),
+        };
+        let nanos = match self {
+            LatencyDistribution::Uniform => uniform(max_nanos),
+            LatencyDistribution::Exponential => {
+                let unit = crate::random::unit_from(&random);
+                let mean = max_nanos as f64 / 4.0;
+                (-(1.0 - unit).ln() * mean).min(max_nanos as f64) as u64
+            }
+            LatencyDistribution::Spike => {
+                if crate::random::below_from(10, &random) == 0 {
+                    max_nanos - uniform(max_nanos / 2)
+                } else {
+                    uniform(max_nanos / 100)
+                }
+            }
+        };
+        Duration::from_nanos(nanos)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FaultSnapshot {
+    enabled: bool,
+    pending_trips: u32,
 }
 
-/// Enables all registered faults.
+/// Restores the state of one or more faults when dropped.
+///
+/// Returned by the scoped methods on [`FaultEntry`] and by [`only_enable`].
+/// Because restoration happens in `Drop`, faults are also restored when a test
+/// panics, so a failing test can't leak fault configuration into later tests.
+#[derive(Debug)]
+pub struct FaultGuard<'a> {
+    saved: Vec<(&'a FaultEntry, FaultSnapshot)>,
+}
+
+impl<'a> FaultGuard<'a> {
+    fn save(faults: impl IntoIterator<Item = &'a FaultEntry>) -> Self {
+        let saved = faults
+            .into_iter()
+            .map(|fault| (fault, fault.snapshot()))
+            .collect();
+        Self { saved }
+    }
+}
+
+impl Drop for FaultGuard<'_> {
+    fn drop(&mut self) {
+        // restore in reverse order so that a fault saved twice ends up in its
+        // earliest recorded state
+        for (fault, snapshot) in self.saved.iter().rev() {
+            fault.restore(snapshot);
+        }
+    }
+}
+
+static DYNAMIC_FAULTS: Mutex<Vec<&'static FaultEntry>> = Mutex::new(Vec::new());
+
+/// Registers a fault at runtime, returning the existing fault if one with the
+/// same name is already registered.
+///
+/// Most faults are registered statically by the fault macros. This is for
+/// faults whose names are only known at runtime, such as faults created per
+/// instance by the wrappers in [`io`](crate::io). Runtime faults are included
+/// in every operation over registered faults, such as [`enable_all`] and
+/// [`get_fault_by_name`]. Each distinct name is leaked for the life of the
+/// process.
+///
+/// Like the fault macros, a newly registered fault registers a `Sometimes`
+/// catalog entry with the dispatcher, which tracks whether it ever trips.
+#[track_caller]
+pub fn register(name: &str) -> &'static FaultEntry {
+    register_with(name, true)
+}
+
+/// Like [`register`], but sets whether a newly registered fault starts out
+/// enabled.
+#[track_caller]
+pub(crate) fn register_with(name: &str, enabled: bool) -> &'static FaultEntry {
+    static FUNCTION: LazyLock<&'static str> = LazyLock::new(|| "precept::fault::register");
+
+    let mut dynamic = DYNAMIC_FAULTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let existing = FAULT_CATALOG.into_iter().chain(dynamic.iter().copied());
+    if let Some(entry) = existing.into_iter().find(|entry| entry.name == name) {
+        return entry;
+    }
+    let name: &'static str = Box::leak(name.into());
+    let property: &'static str = Box::leak(format!("precept fault: {name}").into());
+    let catalog_entry: &'static CatalogEntry = Box::leak(Box::new(CatalogEntry::new(
+        Expectation::Sometimes,
+        property,
+        Location::caller(),
+        module_path!(),
+        &FUNCTION,
+    )));
+    let entry: &'static FaultEntry = Box::leak(Box::new(FaultEntry {
+        entry: Some(catalog_entry),
+        ..FaultEntry::new(name)
+            .with_property(property)
+            .with_enabled(enabled)
+    }));
+    dynamic.push(entry);
+    drop(dynamic);
+    crate::dispatch::emit(Event::RegisterEntry(catalog_entry));
+    entry
+}
+
+/// Returns every registered fault, including faults registered at runtime via
+/// [`register`].
+pub fn all_faults() -> impl Iterator<Item = &'static FaultEntry> {
+    let dynamic = DYNAMIC_FAULTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    FAULT_CATALOG.into_iter().chain(dynamic.clone())
+}
+
+/// Enables all registered faults except crash points, which only fire when
+/// explicitly armed.
 ///
 /// Panics if precept is disabled.
+#[allow(clippy::assertions_on_constants)]
 pub fn enable_all() {
     assert!(ENABLED, "Precept is disabled");
-    for entry in FAULT_CATALOG {
+    for entry in all_faults().filter(|&entry| !crate::crash::is_crash_point(entry)) {
         entry.enable()
     }
 }
@@ -108,14 +462,459 @@ pub fn enable_all() {
 /// Disables all registered faults.
 pub fn disable_all() {
     tracing::warn!("Precept Faults disabled");
-    for entry in FAULT_CATALOG {
+    for entry in all_faults() {
         entry.disable();
     }
 }
 
+/// A limit on the number of random fault trips.
+#[derive(Debug)]
+struct Budget {
+    max_trips: u64,
+    /// if set, `used` resets to zero once this much time has passed since
+    /// `window_start`
+    window: Option<Duration>,
+    window_start: Instant,
+    used: u64,
+}
+
+static HAS_BUDGET: AtomicBool = AtomicBool::new(false);
+static BUDGET: Mutex<Option<Budget>> = Mutex::new(None);
+
+fn replace_budget(budget: Option<Budget>) {
+    let mut guard = BUDGET.lock().unwrap_or_else(PoisonError::into_inner);
+    HAS_BUDGET.store(budget.is_some(), Ordering::Release);
+    *guard = budget;
+}
+
+/// Limits the total number of random fault trips for the rest of the run.
+///
+/// Once `max_trips` faults have randomly tripped, faults stop tripping except
+/// for pending forced trips, giving recovery logic a chance to make progress.
+/// Each trip which consumes the budget is reported as a `precept_fault_budget`
+/// event. Replaces any previously configured budget.
+pub fn set_budget(max_trips: u64) {
+    replace_budget(Some(Budget {
+        max_trips,
+        window: None,
+        window_start: Instant::now(),
+        used: 0,
+    }));
+}
+
+/// Limits the number of random fault trips to `max_trips` per `window` of
+/// time.
+///
+/// This behaves like [`set_budget`], except that the budget is refilled at the
+/// start of each window. Replaces any previously configured budget.
+pub fn set_rate_limit(max_trips: u64, window: Duration) {
+    replace_budget(Some(Budget {
+        max_trips,
+        window: Some(window),
+        window_start: Instant::now(),
+        used: 0,
+    }));
+}
+
+/// Removes the fault budget, allowing faults to trip without limit.
+pub fn clear_budget() {
+    replace_budget(None);
+}
+
+/// Returns the number of random trips remaining in the current budget, or
+/// `None` if there is no budget.
+pub fn budget_remaining() -> Option<u64> {
+    let mut guard = BUDGET.lock().unwrap_or_else(PoisonError::into_inner);
+    guard.as_mut().map(|budget| {
+        budget.refill();
+        budget.max_trips.saturating_sub(budget.used)
+    })
+}
+
+impl Budget {
+    fn refill(&mut self) {
+        if let Some(window) = self.window {
+            let now = Instant::now();
+            if now.duration_since(self.window_start) >= window {
+                self.window_start = now;
+                self.used = 0;
+            }
+        }
+    }
+}
+
+/// Consumes one trip from the budget on behalf of `fault`, returning false if
+/// the budget is exhausted.
+fn consume_budget(fault: &FaultEntry) -> bool {
+    if !HAS_BUDGET.load(Ordering::Acquire) {
+        return true;
+    }
+    let consumed = {
+        let mut guard = BUDGET.lock().unwrap_or_else(PoisonError::into_inner);
+        match guard.as_mut() {
+            None => return true,
+            Some(budget) => {
+                budget.refill();
+                (budget.used < budget.max_trips).then(|| {
+                    budget.used += 1;
+                    (budget.used, budget.max_trips)
+                })
+            }
+        }
+    };
+    // emit outside of the lock in case the dispatcher trips a fault
+    if let Some((used, max_trips)) = consumed {
+        crate::dispatch::emit(crate::dispatch::Event::Custom {
+            name: "precept_fault_budget",
+            value: serde_json::json!({
+                "fault": fault.name,
+                "used": used,
+                "max_trips": max_trips,
+            }),
+        });
+    }
+    consumed.is_some()
+}
+
+/// The environment variable which, when set to `1` or `true`, causes
+/// [`init`](crate::init) to run [`swarm`].
+pub const SWARM_ENV: &str = "PRECEPT_FAULT_SWARM";
+
+/// The label of the substream [`swarm`] draws its selection from.
+pub const SWARM_LABEL: &str = "fault.swarm";
+
+pub(crate) fn init_swarm() {
+    if std::env::var(SWARM_ENV).is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true")) {
+        swarm();
+    }
+}
+
+/// Enables a random subset of the registered faults and disables the rest.
+///
+/// Swarm testing explores more configurations by varying which kinds of faults
+/// are active in each run rather than enabling every fault at once. Each fault
+/// is enabled with probability 1/2, drawn from the dispatcher's
+/// [`SWARM_LABEL`] substream, so the selection is driven by the dispatcher's
+/// seed: a [`SeededDispatch`](crate::dispatch::seeded::SeededDispatch) with
+/// the same seed selects the same faults.
+///
+/// The chosen configuration is emitted as a `precept_fault_swarm` event so that
+/// a run can be reproduced with [`only_enable`]. Returns the enabled faults.
+///
+/// Crash points are left untouched, since they only fire when explicitly
+/// armed.
+pub fn swarm() -> Vec<&'static FaultEntry> {
+    let mut enabled = vec![];
+    let mut disabled = vec![];
+    for entry in all_faults().filter(|&entry| !crate::crash::is_crash_point(entry)) {
+        if random_trip(SWARM_LABEL) {
+            entry.enable();
+            enabled.push(entry);
+        } else {
+            entry.disable();
+            disabled.push(entry.name);
+        }
+    }
+    crate::dispatch::emit(crate::dispatch::Event::Custom {
+        name: "precept_fault_swarm",
+        value: serde_json::json!({
+            "enabled": enabled.iter().map(|entry| entry.name).collect::<Vec<_>>(),
+            "disabled": disabled,
+        }),
+    });
+    enabled
+}
+
+/// Enables exactly the given faults and disables every other registered fault
+/// until the returned guard is dropped.
+///
+/// Pending forced trips are left untouched, so [`FaultEntry::set_pending`]
+/// still works on faults outside of `faults`.
+#[must_use = "the faults are restored as soon as the guard is dropped"]
+pub fn only_enable<'a>(faults: &[&'a FaultEntry]) -> FaultGuard<'a> {
+    let listed = |entry: &FaultEntry| faults.iter().any(|f| std::ptr::eq(*f, entry));
+    let mut guard = FaultGuard::save(faults.iter().copied());
+    let others = all_faults().filter(|&entry| !listed(entry));
+    guard
+        .saved
+        .extend(others.map(|entry| (entry, entry.snapshot())));
+    for (fault, _) in &guard.saved {
+        if listed(fault) {
+            fault.enable();
+        } else {
+            fault.disable();
+        }
+    }
+    guard
+}
+
 /// Looks up a fault entry by its name.
 ///
 /// Returns `None` if no fault with the given name exists.
 pub fn get_fault_by_name(name: &str) -> Option<&'static FaultEntry> {
-    FAULT_CATALOG.into_iter().find(|&entry| entry.name == name)
+    all_faults().find(|&entry| entry.name == name)
+}
+
+/// Serializes tests which change the state of faults in the global catalog or
+/// depend on global fault configuration such as the budget.
+#[cfg(test)]
+pub(crate) fn lock_catalog() -> std::sync::MutexGuard<'static, ()> {
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    LOCK.lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use std::time::Duration;
+
+    use super::{FaultEntry, LatencyDistribution, only_enable, trip_choice};
+
+    #[test]
+    fn force_scoped_restores_state() {
+        static FAULT: FaultEntry = FaultEntry::new("force_scoped_restores_state");
+        FAULT.disable();
+        FAULT.set_pending(1);
+        {
+            let _guard = FAULT.force_scoped(3);
+            assert_eq!(FAULT.count_pending(), 3);
+            assert!(FAULT.trip());
+        }
+        assert_eq!(FAULT.count_pending(), 1);
+        assert!(!FAULT.enabled.load(super::Ordering::Acquire));
+    }
+
+    #[test]
+    fn disable_scoped_restores_on_panic() {
+        static FAULT: FaultEntry = FaultEntry::new("disable_scoped_restores_on_panic");
+        let result = panic::catch_unwind(|| {
+            let _guard = FAULT.disable_scoped();
+            assert!(!FAULT.trip());
+            panic!("test failure");
+        });
+        assert!(result.is_err());
+        assert!(FAULT.enabled.load(super::Ordering::Acquire));
+    }
+
+    #[test]
+    fn trip_choice_respects_pending_and_enabled() {
+        static A: FaultEntry = FaultEntry::new("trip_choice_a");
+        static B: FaultEntry = FaultEntry::new("trip_choice_b");
+        static C: FaultEntry = FaultEntry::new("trip_choice_c");
+        let alternatives = [(&A, 1), (&B, 1), (&C, 0)];
+
+        let _forced = C.force_scoped(1);
+        assert!(std::ptr::eq(trip_choice(&alternatives).unwrap(), &C));
+
+        B.set_requires_setup(false);
+        let _disabled = A.disable_scoped();
+        for _ in 0..100 {
+            if let Some(chosen) = trip_choice(&alternatives) {
+                assert!(std::ptr::eq(chosen, &B));
+            }
+        }
+
+        let _disabled = B.disable_scoped();
+        assert!(trip_choice(&alternatives).is_none());
+    }
+
+    #[test]
+    fn requires_setup_gates_random_trips() {
+        let _lock = super::lock_catalog();
+        static UNGATED: FaultEntry = FaultEntry::new("requires_setup_ungated");
+        UNGATED.set_requires_setup(false);
+        assert!((0..100).any(|_| UNGATED.trip()));
+
+        static GATED: FaultEntry = FaultEntry::new("requires_setup_gated");
+        assert!(GATED.requires_setup());
+        let _setup = crate::dispatch::setup_scoped(false);
+        assert!(!(0..100).any(|_| GATED.trip()));
+        // forced trips ignore setup
+        let _forced = GATED.force_scoped(1);
+        assert!(GATED.trip());
+
+        crate::dispatch::emit(crate::dispatch::Event::SetupComplete {
+            details: serde_json::Value::Null,
+        });
+        assert!(crate::dispatch::is_setup_complete());
+        assert!((0..100).any(|_| GATED.trip()));
+    }
+
+    #[test]
+    fn budget_limits_random_trips() {
+        static FAULT: FaultEntry = FaultEntry::new("budget_limits_random_trips");
+        FAULT.set_requires_setup(false);
+
+        let _lock = super::lock_catalog();
+        super::set_budget(2);
+        let trips = (0..100).filter(|_| FAULT.trip()).count();
+        assert_eq!(trips, 2);
+        assert_eq!(super::budget_remaining(), Some(0));
+
+        // forced trips ignore the budget
+        let _forced = FAULT.force_scoped(1);
+        assert!(FAULT.trip());
+
+        super::set_rate_limit(1, Duration::from_millis(50));
+        assert_eq!((0..100).filter(|_| FAULT.trip()).count(), 1);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(super::budget_remaining(), Some(1));
+
+        super::clear_budget();
+        assert_eq!(super::budget_remaining(), None);
+    }
+
+    #[test]
+    fn trips_are_recorded() {
+        static FAULT: FaultEntry = FaultEntry::new("trips_are_recorded");
+        let _disabled = FAULT.disable_scoped();
+        let _forced = FAULT.force_scoped(1);
+        let line = line!() + 1;
+        assert!(FAULT.trip());
+
+        let decisions = super::recent_decisions();
+        let decision = decisions
+            .iter()
+            .rev()
+            .find(|d| d.name() == FAULT.name())
+            .unwrap();
+        assert_eq!(decision.kind(), super::TripKind::Forced);
+        assert_eq!(decision.location().file(), file!());
+        assert_eq!(decision.location().line(), line);
+        assert_eq!(decision.thread(), std::thread::current().name().unwrap());
+
+        let details = super::attach_recent_decisions(serde_json::json!({ "key": 123 }));
+        assert_eq!(details["key"], 123);
+        assert!(details["precept_fault_decisions"].as_array().is_some());
+        let details = super::attach_recent_decisions(serde_json::Value::Null);
+        assert!(details["details"].is_null());
+    }
+
+    #[test]
+    fn latency_sample_is_bounded() {
+        let max = Duration::from_millis(200);
+        for distribution in [
+            LatencyDistribution::Uniform,
+            LatencyDistribution::Exponential,
+            LatencyDistribution::Spike,
+        ] {
+            for _ in 0..1000 {
+                assert!(distribution.sample(max) <= max);
+            }
+            assert_eq!(distribution.sample(Duration::ZERO), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn swarm_enables_a_subset() {
+        let _lock = super::lock_catalog();
+        let _guard = only_enable(&[]);
+        let enabled = super::swarm();
+        assert!(!enabled.iter().any(|e| crate::crash::is_crash_point(e)));
+        for entry in super::all_faults() {
+            let listed = enabled.iter().any(|e| std::ptr::eq(*e, entry));
+            assert_eq!(entry.is_enabled(), listed);
+        }
+
+        #[cfg(feature = "enabled")]
+        {
+            use crate::dispatch::{dispatch_scoped, noop::NoopDispatch, seeded::SeededDispatch};
+
+            static NOOP: NoopDispatch = NoopDispatch;
+            let seeded_swarm = || {
+                let dispatch = Box::leak(Box::new(SeededDispatch::new(7, &NOOP)));
+                let _scoped = dispatch_scoped(dispatch);
+                super::swarm()
+            };
+            let first = seeded_swarm();
+            assert!(
+                first
+                    .iter()
+                    .map(|e| e.name())
+                    .eq(seeded_swarm().iter().map(|e| e.name()))
+            );
+        }
+    }
+
+    #[test]
+    fn register_returns_existing_faults() {
+        let fault = super::register("register_returns_existing_faults");
+        assert_eq!(fault.name(), "register_returns_existing_faults");
+        assert_eq!(
+            fault.property(),
+            "precept fault: register_returns_existing_faults"
+        );
+        assert!(std::ptr::eq(
+            fault,
+            super::register("register_returns_existing_faults")
+        ));
+        let found = super::get_fault_by_name("register_returns_existing_faults").unwrap();
+        assert!(std::ptr::eq(fault, found));
+    }
+
+    #[cfg(feature = "enabled")]
+    #[test]
+    fn runtime_faults_register_catalog_entries() {
+        use std::sync::Mutex;
+
+        use crate::{
+            catalog::Expectation,
+            dispatch::{Dispatch, Event, dispatch_scoped},
+        };
+
+        /// Records the property of each registered entry and each emitted
+        /// condition.
+        #[derive(Default)]
+        struct Recording(Mutex<Vec<(&'static str, Option<bool>)>>);
+
+        impl Dispatch for Recording {
+            fn emit(&self, event: Event) {
+                let record = match event {
+                    Event::RegisterEntry(entry) => {
+                        assert_eq!(entry.expectation(), Expectation::Sometimes);
+                        (entry.property(), None)
+                    }
+                    Event::EmitEntry { entry, condition, .. } => {
+                        (entry.property(), Some(condition))
+                    }
+                    _ => return,
+                };
+                self.0.lock().unwrap().push(record);
+            }
+
+            fn random(&self) -> u64 {
+                rand::random()
+            }
+        }
+
+        let recording: &'static Recording = Box::leak(Box::default());
+        let _scoped = dispatch_scoped(recording);
+        let _lock = super::lock_catalog();
+        let fault = super::register("runtime_faults_register_catalog_entries");
+        let _forced = fault.force_scoped(1);
+        assert!(fault.trip());
+
+        let property = "precept fault: runtime_faults_register_catalog_entries";
+        assert_eq!(
+            *recording.0.lock().unwrap(),
+            [(property, None), (property, Some(true))]
+        );
+    }
+
+    #[test]
+    fn only_enable_restores_state() {
+        static LISTED: FaultEntry = FaultEntry::new("only_enable_listed");
+        let _lock = super::lock_catalog();
+        LISTED.disable();
+        {
+            let _guard = only_enable(&[&LISTED]);
+            assert!(LISTED.enabled.load(super::Ordering::Acquire));
+            for entry in super::all_faults() {
+                assert!(!entry.enabled.load(super::Ordering::Acquire));
+            }
+        }
+        assert!(!LISTED.enabled.load(super::Ordering::Acquire));
+    }
 }
@@ -0,0 +1,132 @@
+use std::{
+    collections::VecDeque,
+    panic::Location,
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+};
+
+use serde_json::{Value, json};
+
+use super::FaultEntry;
+
+/// The maximum number of decisions retained by the decision log.
+pub const DECISION_LOG_CAPACITY: usize = 256;
+
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+static DECISIONS: Mutex<VecDeque<FaultDecision>> = Mutex::new(VecDeque::new());
+
+/// Why a fault tripped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TripKind {
+    /// The fault consumed a pending forced trip.
+    Forced,
+    /// The fault tripped due to the dispatcher's randomness.
+    Random,
+}
+
+/// A record of a single fault trip.
+#[derive(Debug, Clone)]
+pub struct FaultDecision {
+    sequence: u64,
+    name: &'static str,
+    location: &'static Location<'static>,
+    kind: TripKind,
+    thread: String,
+}
+
+impl FaultDecision {
+    /// A process-wide monotonic sequence number ordering all decisions.
+    #[inline]
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// The name of the fault which tripped.
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The location of the fault point which tripped.
+    #[inline]
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    /// Whether the fault was forced or tripped randomly.
+    #[inline]
+    pub fn kind(&self) -> TripKind {
+        self.kind
+    }
+
+    /// The name of the thread the fault tripped on, or its id if it is unnamed.
+    #[inline]
+    pub fn thread(&self) -> &str {
+        &self.thread
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "sequence": self.sequence,
+            "name": self.name,
+            "location": self.location.to_string(),
+            "kind": match self.kind {
+                TripKind::Forced => "forced",
+                TripKind::Random => "random",
+            },
+            "thread": self.thread,
+        })
+    }
+}
+
+pub(super) fn record(fault: &FaultEntry, location: &'static Location<'static>, kind: TripKind) {
+    let current = thread::current();
+    let thread = match current.name() {
+        Some(name) => name.to_owned(),
+        None => format!("{:?}", current.id()),
+    };
+    let mut decisions = DECISIONS.lock().unwrap_or_else(PoisonError::into_inner);
+    // assign the sequence number under the lock so the log stays ordered
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    if decisions.len() == DECISION_LOG_CAPACITY {
+        decisions.pop_front();
+    }
+    decisions.push_back(FaultDecision {
+        sequence,
+        name: fault.name,
+        location,
+        kind,
+        thread,
+    });
+}
+
+/// Returns the most recent fault trips, oldest first.
+///
+/// At most [`DECISION_LOG_CAPACITY`] decisions are retained. The log is
+/// automatically attached to the details of the first failing `Always`
+/// expectation.
+pub fn recent_decisions() -> Vec<FaultDecision> {
+    let decisions = DECISIONS.lock().unwrap_or_else(PoisonError::into_inner);
+    decisions.iter().cloned().collect()
+}
+
+/// Attaches the recent fault decisions to an expectation's details.
+pub(crate) fn attach_recent_decisions(details: Value) -> Value {
+    let decisions: Vec<Value> = recent_decisions()
+        .iter()
+        .map(FaultDecision::to_json)
+        .collect();
+    match details {
+        Value::Object(mut map) => {
+            map.insert("precept_fault_decisions".into(), decisions.into());
+            Value::Object(map)
+        }
+        details => json!({
+            "details": details,
+            "precept_fault_decisions": decisions,
+        }),
+    }
+}
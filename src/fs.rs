@@ -0,0 +1,730 @@
+//! A simulated filesystem which can lose un-synced data when it crashes.
+//!
+//! [`Fs`] keeps two views of every file: the volatile contents observed by
+//! reads, and the durable contents held by a backend, which is either an
+//! in-memory store ([`Fs::memory`]) or a directory on the real filesystem
+//! ([`Fs::real`]). Writes only reach the backend when a file is synced with
+//! [`File::sync_all`] or [`File::sync_data`].
+//!
+//! [`Fs::crash`] simulates a power loss. Each un-synced operation is applied,
+//! dropped, or torn at [`SECTOR_SIZE`] boundaries according to dispatcher
+//! randomness, and files which were never synced may disappear entirely.
+//! Open [`File`] handles are invalidated by a crash.
+//!
+//! The filesystem also registers the [`ENOSPC`], [`EIO`] and
+//! [`RENAME_NOT_ATOMIC`] faults.
+//!
+//! ```
+//! use std::io::{Read, Write};
+//! use precept::fs::Fs;
+//!
+//! let fs = Fs::memory();
+//! let mut file = fs.create("wal").unwrap();
+//! file.write_all(b"committed").unwrap();
+//! file.sync_all().unwrap();
+//! file.write_all(b" and maybe lost").unwrap();
+//!
+//! fs.crash().unwrap();
+//! let data = fs.read("wal").unwrap();
+//! assert!(data.starts_with(b"committed"));
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fs as std_fs,
+    io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::dispatch::{self, Event, get_random_for};
+
+/// The granularity at which un-synced writes are torn by [`Fs::crash`].
+pub const SECTOR_SIZE: usize = 512;
+
+crate::declare_fault! {
+    /// Fails writes, file creation and growing [`File::set_len`] calls with
+    /// [`ErrorKind::StorageFull`].
+    pub ENOSPC = "fs.enospc";
+
+    /// Fails reads, writes and syncs with an I/O error.
+    pub EIO = "fs.eio";
+
+    /// Makes a rename non-atomic: if the filesystem crashes before the renamed
+    /// file is synced, the source is left in place and the destination holds a
+    /// torn copy of it.
+    pub RENAME_NOT_ATOMIC = "fs.rename_not_atomic";
+}
+
+fn enospc() -> io::Result<()> {
+    crate::fault_result!(ENOSPC, || io::Error::new(
+        ErrorKind::StorageFull,
+        "precept injected ENOSPC"
+    ))
+}
+
+fn eio() -> io::Result<()> {
+    crate::fault_result!(EIO, || io::Error::other("precept injected EIO"))
+}
+
+/// Labels the substream [`Fs::crash`] draws its outcomes from.
+const CRASH_LABEL: &str = "fs.crash";
+
+fn coin() -> bool {
+    get_random_for(CRASH_LABEL) % 2 == 0
+}
+
+/// Where durable file contents are stored.
+#[derive(Debug)]
+enum Backend {
+    Memory(BTreeMap<PathBuf, Vec<u8>>),
+    Real(PathBuf),
+}
+
+impl Backend {
+    fn load(&self, path: &Path) -> io::Result<Option<Vec<u8>>> {
+        match self {
+            Backend::Memory(files) => Ok(files.get(path).cloned()),
+            Backend::Real(root) => match std_fs::read(root.join(path)) {
+                Ok(data) => Ok(Some(data)),
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err),
+            },
+        }
+    }
+
+    fn store(&mut self, path: &Path, data: &[u8]) -> io::Result<()> {
+        match self {
+            Backend::Memory(files) => {
+                files.insert(path.to_owned(), data.to_owned());
+                Ok(())
+            }
+            Backend::Real(root) => {
+                let path = root.join(path);
+                if let Some(parent) = path.parent() {
+                    std_fs::create_dir_all(parent)?;
+                }
+                let mut file = std_fs::File::create(path)?;
+                file.write_all(data)?;
+                file.sync_all()
+            }
+        }
+    }
+
+    fn remove(&mut self, path: &Path) -> io::Result<()> {
+        match self {
+            Backend::Memory(files) => {
+                files.remove(path);
+                Ok(())
+            }
+            Backend::Real(root) => match std_fs::remove_file(root.join(path)) {
+                Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            },
+        }
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        match self {
+            Backend::Memory(files) => {
+                if let Some(data) = files.remove(from) {
+                    files.insert(to.to_owned(), data);
+                }
+                Ok(())
+            }
+            Backend::Real(root) => {
+                let to = root.join(to);
+                if let Some(parent) = to.parent() {
+                    std_fs::create_dir_all(parent)?;
+                }
+                std_fs::rename(root.join(from), to)
+            }
+        }
+    }
+}
+
+/// An operation which has not yet been synced.
+#[derive(Debug)]
+enum Op {
+    Write { offset: u64, data: Vec<u8> },
+    SetLen(u64),
+}
+
+#[derive(Debug)]
+struct Inode {
+    /// The path of the file, or `None` once it has been removed.
+    path: Option<PathBuf>,
+    /// The volatile contents of the file.
+    data: Vec<u8>,
+    /// Operations applied to `data` since the last sync.
+    ops: Vec<Op>,
+    /// Whether the backend holds a copy of the file.
+    durable: bool,
+    /// The durable path of a file whose rename tripped
+    /// [`RENAME_NOT_ATOMIC`] and has not been synced since.
+    renamed_from: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+struct State {
+    backend: Backend,
+    paths: BTreeMap<PathBuf, u64>,
+    inodes: BTreeMap<u64, Inode>,
+    next_id: u64,
+}
+
+impl State {
+    /// Returns the inode of `path`, loading it from the backend if needed.
+    fn lookup(&mut self, path: &Path) -> io::Result<Option<u64>> {
+        if let Some(&id) = self.paths.get(path) {
+            return Ok(Some(id));
+        }
+        if self.renamed_from(path).is_some() {
+            return Ok(None);
+        }
+        match self.backend.load(path)? {
+            Some(data) => Ok(Some(self.insert(path, data, true))),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the inode whose non-atomic rename from `path` is pending.
+    fn renamed_from(&mut self, path: &Path) -> Option<&mut Inode> {
+        self.inodes
+            .values_mut()
+            .find(|inode| inode.renamed_from.as_deref() == Some(path))
+    }
+
+    /// Completes a pending non-atomic rename from `path` so that `path` can
+    /// be reused.
+    fn commit_rename(&mut self, path: &Path) -> io::Result<()> {
+        if let Some(inode) = self.renamed_from(path) {
+            inode.renamed_from = None;
+            let to = inode.path.clone().expect("renamed inode has a path");
+            self.backend.rename(path, &to)?;
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, path: &Path, data: Vec<u8>, durable: bool) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.paths.insert(path.to_owned(), id);
+        self.inodes.insert(
+            id,
+            Inode {
+                path: Some(path.to_owned()),
+                data,
+                ops: vec![],
+                durable,
+                renamed_from: None,
+            },
+        );
+        id
+    }
+
+    /// Removes `path` from the namespace. Removal is immediately durable.
+    fn unlink(&mut self, path: &Path) -> io::Result<()> {
+        if let Some(id) = self.paths.remove(path) {
+            let inode = self.inodes.get_mut(&id).expect("inode exists");
+            if inode.durable {
+                if let Some(from) = inode.renamed_from.take() {
+                    self.backend.remove(&from)?;
+                }
+                self.backend.remove(path)?;
+            }
+            inode.path = None;
+            inode.durable = false;
+        } else {
+            self.commit_rename(path)?;
+            self.backend.remove(path)?;
+        }
+        Ok(())
+    }
+
+    fn inode(&mut self, id: u64) -> io::Result<&mut Inode> {
+        self.inodes
+            .get_mut(&id)
+            .ok_or_else(|| io::Error::other("stale file handle after a simulated crash"))
+    }
+}
+
+/// A simulated filesystem. Cloning an `Fs` returns another handle to the
+/// same filesystem.
+#[derive(Debug, Clone)]
+pub struct Fs {
+    state: Arc<Mutex<State>>,
+}
+
+impl Fs {
+    fn new(backend: Backend) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                backend,
+                paths: BTreeMap::new(),
+                inodes: BTreeMap::new(),
+                next_id: 0,
+            })),
+        }
+    }
+
+    /// Creates an empty filesystem whose durable contents are kept in memory.
+    pub fn memory() -> Self {
+        Self::new(Backend::Memory(BTreeMap::new()))
+    }
+
+    /// Creates a filesystem whose durable contents are kept in files under
+    /// `root`, which is created if it doesn't exist.
+    ///
+    /// Un-synced data is only kept in memory, so it is lost if the process
+    /// exits, for example at a [`crash_point!`](crate::crash_point).
+    pub fn real(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+        std_fs::create_dir_all(&root)?;
+        Ok(Self::new(Backend::Real(root)))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Opens an existing file for reading and writing.
+    pub fn open(&self, path: impl AsRef<Path>) -> io::Result<File> {
+        let path = path.as_ref();
+        match self.lock().lookup(path)? {
+            Some(id) => Ok(File { fs: self.clone(), id, pos: 0 }),
+            None => Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("{} not found", path.display()),
+            )),
+        }
+    }
+
+    /// Creates a file for reading and writing, truncating it if it exists.
+    pub fn create(&self, path: impl AsRef<Path>) -> io::Result<File> {
+        let path = path.as_ref();
+        enospc()?;
+        let mut state = self.lock();
+        let id = match state.lookup(path)? {
+            Some(id) => {
+                let inode = state.inode(id)?;
+                inode.data.clear();
+                inode.ops.push(Op::SetLen(0));
+                id
+            }
+            None => {
+                state.commit_rename(path)?;
+                state.insert(path, vec![], false)
+            }
+        };
+        Ok(File { fs: self.clone(), id, pos: 0 })
+    }
+
+    /// Returns true if `path` exists.
+    pub fn exists(&self, path: impl AsRef<Path>) -> io::Result<bool> {
+        Ok(self.lock().lookup(path.as_ref())?.is_some())
+    }
+
+    /// Reads the entire contents of a file.
+    pub fn read(&self, path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+        let mut data = vec![];
+        self.open(path)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// Removes a file.
+    pub fn remove_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut state = self.lock();
+        if state.lookup(path)?.is_none() {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("{} not found", path.display()),
+            ));
+        }
+        state.unlink(path)
+    }
+
+    /// Renames a file, replacing `to` if it exists.
+    ///
+    /// Renames are atomic and immediately durable unless
+    /// [`RENAME_NOT_ATOMIC`] trips.
+    pub fn rename(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let mut state = self.lock();
+        let Some(id) = state.lookup(from)? else {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("{} not found", from.display()),
+            ));
+        };
+        if from == to {
+            return Ok(());
+        }
+        state.unlink(to)?;
+        state.paths.remove(from);
+        state.paths.insert(to.to_owned(), id);
+
+        let State { backend, inodes, .. } = &mut *state;
+        let inode = inodes.get_mut(&id).expect("inode exists");
+        inode.path = Some(to.to_owned());
+        if inode.durable {
+            let durable_from = inode.renamed_from.take().unwrap_or_else(|| from.to_owned());
+            if crate::fault_result!(RENAME_NOT_ATOMIC, || ()).is_err() {
+                inode.renamed_from = Some(durable_from);
+            } else {
+                backend.rename(&durable_from, to)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Simulates a power loss.
+    ///
+    /// Every un-synced operation is independently applied, dropped, or torn
+    /// at sector boundaries before being persisted to the backend, and files
+    /// which were never synced survive with a 50% chance. Afterwards the
+    /// filesystem reflects only the durable contents and every open [`File`]
+    /// is invalidated.
+    ///
+    /// The outcome is reported as a `precept_fs_crash` event.
+    /// If the backend fails, the remaining files are still crashed and the
+    /// first error is returned.
+    pub fn crash(&self) -> io::Result<()> {
+        let mut state = self.lock();
+        let State { backend, inodes, paths, .. } = &mut *state;
+        let mut outcome = CrashOutcome::default();
+        let mut result = Ok(());
+
+        // every inode is crashed even if the backend fails partway, so the
+        // filesystem always ends up in a post-crash state
+        for inode in inodes.values_mut() {
+            let crashed = crash_inode(backend, inode, &mut outcome);
+            result = result.and(crashed);
+        }
+        inodes.clear();
+        paths.clear();
+        drop(state);
+
+        dispatch::emit(Event::Custom {
+            name: "precept_fs_crash",
+            value: serde_json::json!({
+                "applied": outcome.applied,
+                "dropped": outcome.dropped,
+                "torn": outcome.torn,
+                "lost_files": outcome.lost_files,
+            }),
+        });
+        result
+    }
+}
+
+/// Counts what happened to un-synced operations during [`Fs::crash`].
+#[derive(Debug, Default)]
+struct CrashOutcome {
+    applied: u64,
+    dropped: u64,
+    torn: u64,
+    lost_files: u64,
+}
+
+/// Persists the un-synced operations of `inode` which survive a crash.
+fn crash_inode(
+    backend: &mut Backend,
+    inode: &mut Inode,
+    outcome: &mut CrashOutcome,
+) -> io::Result<()> {
+    let Some(path) = inode.path.as_deref() else {
+        return Ok(());
+    };
+    if let Some(from) = inode.renamed_from.as_deref() {
+        // the rename was torn: the source survives and the destination
+        // holds a prefix of its contents
+        let source = backend.load(from)?.unwrap_or_default();
+        let sectors = source.len().div_ceil(SECTOR_SIZE) as u64;
+        let keep = crate::random::range_for(CRASH_LABEL, 0..=sectors) as usize * SECTOR_SIZE;
+        outcome.torn += 1;
+        return backend.store(path, &source[..keep.min(source.len())]);
+    }
+    if inode.ops.is_empty() && inode.durable {
+        return Ok(());
+    }
+    if !inode.durable && coin() {
+        outcome.lost_files += 1;
+        return Ok(());
+    }
+    let mut data = if inode.durable {
+        backend.load(path)?.unwrap_or_default()
+    } else {
+        vec![]
+    };
+    for op in inode.ops.drain(..) {
+        match (op, crate::random::range_for(CRASH_LABEL, 0..3)) {
+            (_, 0) => outcome.dropped += 1,
+            (Op::SetLen(len), _) => {
+                data.resize(len as usize, 0);
+                outcome.applied += 1;
+            }
+            (Op::Write { offset, data: bytes }, 1) => {
+                write_at(&mut data, offset, &bytes);
+                outcome.applied += 1;
+            }
+            (Op::Write { offset, data: bytes }, _) => {
+                tear_at(&mut data, offset, &bytes);
+                outcome.torn += 1;
+            }
+        }
+    }
+    backend.store(path, &data)
+}
+
+/// Writes `bytes` into `data` at `offset`, zero-filling any gap.
+fn write_at(data: &mut Vec<u8>, offset: u64, bytes: &[u8]) {
+    let offset = offset as usize;
+    let end = offset + bytes.len();
+    if data.len() < end {
+        data.resize(end, 0);
+    }
+    data[offset..end].copy_from_slice(bytes);
+}
+
+/// Writes a random subset of the sectors spanned by `bytes` into `data`.
+fn tear_at(data: &mut Vec<u8>, offset: u64, bytes: &[u8]) {
+    let mut pos = offset as usize;
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let len = (SECTOR_SIZE - pos % SECTOR_SIZE).min(rest.len());
+        if coin() {
+            write_at(data, pos as u64, &rest[..len]);
+        }
+        pos += len;
+        rest = &rest[len..];
+    }
+}
+
+/// An open file on a simulated [`Fs`], mirroring [`std::fs::File`].
+#[derive(Debug)]
+pub struct File {
+    fs: Fs,
+    id: u64,
+    pos: u64,
+}
+
+impl File {
+    /// Flushes all un-synced data and metadata to the backend.
+    pub fn sync_all(&self) -> io::Result<()> {
+        eio()?;
+        let mut state = self.fs.lock();
+        let State { backend, inodes, .. } = &mut *state;
+        let inode = inodes
+            .get_mut(&self.id)
+            .ok_or_else(|| io::Error::other("stale file handle after a simulated crash"))?;
+        if let Some(path) = inode.path.as_deref() {
+            backend.store(path, &inode.data)?;
+            if let Some(from) = inode.renamed_from.take() {
+                backend.remove(&from)?;
+            }
+            inode.durable = true;
+        }
+        inode.ops.clear();
+        Ok(())
+    }
+
+    /// Flushes all un-synced data to the backend. This is equivalent to
+    /// [`sync_all`](Self::sync_all).
+    pub fn sync_data(&self) -> io::Result<()> {
+        self.sync_all()
+    }
+
+    /// Truncates or extends the file to `size` bytes.
+    pub fn set_len(&self, size: u64) -> io::Result<()> {
+        let mut state = self.fs.lock();
+        let inode = state.inode(self.id)?;
+        if size > inode.data.len() as u64 {
+            enospc()?;
+        }
+        inode.data.resize(size as usize, 0);
+        inode.ops.push(Op::SetLen(size));
+        Ok(())
+    }
+
+    /// Returns the current length of the file.
+    pub fn len(&self) -> io::Result<u64> {
+        Ok(self.fs.lock().inode(self.id)?.data.len() as u64)
+    }
+
+    /// Returns true if the file is empty.
+    pub fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        eio()?;
+        let mut state = self.fs.lock();
+        let data = &state.inode(self.id)?.data;
+        let start = (self.pos as usize).min(data.len());
+        let n = buf.len().min(data.len() - start);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        eio()?;
+        enospc()?;
+        let mut state = self.fs.lock();
+        let inode = state.inode(self.id)?;
+        write_at(&mut inode.data, self.pos, buf);
+        inode
+            .ops
+            .push(Op::Write { offset: self.pos, data: buf.to_owned() });
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len()?.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = pos
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "seek to a negative offset"))?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Seek, SeekFrom, Write},
+        path::PathBuf,
+    };
+
+    use super::{Fs, SECTOR_SIZE};
+
+    fn check_crash_consistency(fs: &Fs) {
+        let _lock = crate::fault::lock_catalog();
+        let _quiet = crate::fault::only_enable(&[]);
+        let mut file = fs.create("data").unwrap();
+        file.write_all(&[0xAA; SECTOR_SIZE * 4]).unwrap();
+        file.sync_all().unwrap();
+
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(&[0xBB; SECTOR_SIZE * 4]).unwrap();
+        let mut scratch = fs.create("scratch").unwrap();
+        scratch.write_all(b"never synced").unwrap();
+        fs.crash().unwrap();
+
+        let data = fs.read("data").unwrap();
+        assert_eq!(data.len(), SECTOR_SIZE * 4);
+        for sector in data.chunks(SECTOR_SIZE) {
+            assert!(
+                sector.iter().all(|&b| b == sector[0]) && [0xAA, 0xBB].contains(&sector[0]),
+                "sector was partially written"
+            );
+        }
+        if fs.exists("scratch").unwrap() {
+            assert!(b"never synced".starts_with(&fs.read("scratch").unwrap()));
+        }
+        assert!(file.write_all(b"stale").is_err());
+    }
+
+    #[test]
+    fn memory_crash_consistency() {
+        for _ in 0..16 {
+            check_crash_consistency(&Fs::memory());
+        }
+    }
+
+    /// A temporary directory which is removed when dropped, even if the test
+    /// fails.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = format!("precept-fs-{name}-{}", std::process::id());
+            Self(std::env::temp_dir().join(dir))
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn real_crash_consistency() {
+        let root = TempDir::new("consistency");
+        check_crash_consistency(&Fs::real(&root.0).unwrap());
+        assert_eq!(
+            std::fs::read(root.0.join("data")).unwrap().len(),
+            SECTOR_SIZE * 4
+        );
+    }
+
+    #[test]
+    fn crash_finishes_despite_backend_errors() {
+        let _lock = crate::fault::lock_catalog();
+        let _quiet = crate::fault::only_enable(&[]);
+        let root = TempDir::new("backend-errors");
+        let fs = Fs::real(&root.0).unwrap();
+        // crashed first, and fails because its parent becomes a regular file
+        let mut blocked = fs.create("blocked/file").unwrap();
+        blocked.sync_all().unwrap();
+        blocked.write_all(b"x").unwrap();
+        std::fs::remove_dir_all(root.0.join("blocked")).unwrap();
+        std::fs::write(root.0.join("blocked"), b"").unwrap();
+        let mut file = fs.create("data").unwrap();
+        file.write_all(&[0xAA; SECTOR_SIZE]).unwrap();
+        file.sync_all().unwrap();
+        file.write_all(&[0xBB; SECTOR_SIZE]).unwrap();
+
+        assert!(fs.crash().is_err());
+        // the later file was still crashed, leaving only what was persisted
+        assert_eq!(
+            fs.read("data").unwrap(),
+            std::fs::read(root.0.join("data")).unwrap()
+        );
+    }
+
+    #[cfg(feature = "enabled")]
+    #[test]
+    fn fs_faults() {
+        use std::io::ErrorKind;
+
+        let _lock = crate::fault::lock_catalog();
+        let _quiet = crate::fault::only_enable(&[]);
+        let fs = Fs::memory();
+        let mut file = fs.create("a").unwrap();
+        file.write_all(&[1; SECTOR_SIZE * 2]).unwrap();
+        file.sync_all().unwrap();
+
+        let _forced = super::ENOSPC.force_scoped(1);
+        let err = file.write(b"x").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::StorageFull);
+
+        let _forced = super::EIO.force_scoped(1);
+        assert!(file.sync_all().is_err());
+
+        let _forced = super::RENAME_NOT_ATOMIC.force_scoped(1);
+        fs.rename("a", "b").unwrap();
+        assert!(!fs.exists("a").unwrap());
+        fs.crash().unwrap();
+        assert_eq!(fs.read("a").unwrap(), [1; SECTOR_SIZE * 2]);
+        let torn = fs.read("b").unwrap();
+        assert_eq!(torn.len() % SECTOR_SIZE, 0);
+        assert!(torn.iter().all(|&b| b == 1));
+    }
+}
@@ -0,0 +1,189 @@
+//! Interleaving perturbation points which shake out race conditions.
+//!
+//! Threads tend to run in a consistent order, so races rarely show up in
+//! tests. A yield point, registered with [`yield_point!`](crate::yield_point)
+//! or [`yield_point_async!`](crate::yield_point_async), perturbs the schedule
+//! by randomly choosing one of the [`Perturbation`]s: continuing, yielding the
+//! thread, a short sleep or a spin.
+//!
+//! Yield points are registered in the fault catalog, so they can be disabled
+//! and forced like any other fault, and each reports a `Sometimes`
+//! expectation so guidance can see which yield points have perturbed the
+//! schedule. Unlike faults they don't consume the fault budget or record
+//! decisions, as perturbing the schedule never changes a program's
+//! correctness.
+//!
+//! ```
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//!
+//! fn increment(counter: &AtomicU64) {
+//!     let value = counter.load(Ordering::Acquire);
+//!     precept::yield_point!("between_load_and_store");
+//!     counter.store(value + 1, Ordering::Release);
+//! }
+//! # increment(&AtomicU64::new(0));
+//! ```
+//!
+//! When the `enabled` feature is off yield points compile to nothing.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    thread,
+    time::Duration,
+};
+
+use crate::fault::FaultEntry;
+
+/// Every yield point registered by [`yield_point!`](crate::yield_point) and
+/// [`yield_point_async!`](crate::yield_point_async). Yield points are also
+/// registered in the fault catalog.
+#[cfg(feature = "enabled")]
+#[doc(hidden)]
+#[linkme::distributed_slice]
+pub static YIELD_POINTS: [&'static FaultEntry];
+
+#[cfg(not(feature = "enabled"))]
+#[doc(hidden)]
+pub static YIELD_POINTS: [&&FaultEntry; 0] = [];
+
+/// Returns every registered yield point.
+pub fn yield_points() -> impl Iterator<Item = &'static FaultEntry> {
+    YIELD_POINTS.into_iter().copied()
+}
+
+/// The longest sleep chosen by a yield point, in microseconds.
+const MAX_SLEEP_US: u64 = 1_000;
+
+/// The most spin loop iterations chosen by a yield point.
+const MAX_SPINS: u32 = 10_000;
+
+/// How a yield point perturbs the schedule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Perturbation {
+    /// Carry on without perturbing the schedule.
+    Continue,
+    /// Yield the thread via [`thread::yield_now`], or the task back to its
+    /// executor.
+    Yield,
+    /// Sleep for a short duration.
+    Sleep(Duration),
+    /// Busy-wait for a number of spin loop iterations.
+    Spin(u32),
+}
+
+impl Perturbation {
+    /// Chooses how the yield point `point` perturbs the schedule.
+    ///
+    /// A pending forced trip always perturbs the schedule. Otherwise a
+    /// disabled yield point continues, and an enabled one chooses uniformly
+    /// among the perturbations, including continuing.
+    #[doc(hidden)]
+    pub fn choose(point: &FaultEntry) -> Self {
+        if !crate::ENABLED {
+            return Self::Continue;
+        }
+        let forced = point.take_pending();
+        if !forced && !point.may_trip() {
+            return Self::Continue;
+        }
+        // each part gets its own draw from the point's substream, so the
+        // choice doesn't constrain the length
+        let draw = |range| crate::random::range_for(point.name(), range);
+        match draw(if forced { 1..=3 } else { 0..=3 }) {
+            0 => Self::Continue,
+            1 => Self::Yield,
+            2 => Self::Sleep(Duration::from_micros(draw(1..=MAX_SLEEP_US))),
+            _ => Self::Spin(draw(1..=u64::from(MAX_SPINS)) as u32),
+        }
+    }
+
+    /// Returns true if this perturbs the schedule.
+    pub fn is_perturbed(self) -> bool {
+        self != Self::Continue
+    }
+
+    /// Applies this perturbation to the current thread.
+    #[doc(hidden)]
+    pub fn apply(self) {
+        match self {
+            Self::Continue => {}
+            Self::Yield => thread::yield_now(),
+            Self::Sleep(duration) => thread::sleep(duration),
+            Self::Spin(spins) => spin(spins),
+        }
+    }
+
+    /// Applies this perturbation to the current task.
+    ///
+    /// Spins still busy-wait, briefly holding up the executor's thread.
+    #[doc(hidden)]
+    pub async fn apply_async(self) {
+        match self {
+            Self::Continue => {}
+            Self::Yield => YieldNow { yielded: false }.await,
+            Self::Sleep(duration) => crate::timer::sleep(duration).await,
+            Self::Spin(spins) => spin(spins),
+        }
+    }
+}
+
+fn spin(spins: u32) {
+    for _ in 0..spins {
+        std::hint::spin_loop();
+    }
+}
+
+/// Returns `Pending` once, immediately waking the task.
+struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[cfg(all(test, feature = "enabled"))]
+mod tests {
+    use super::{Perturbation, yield_points};
+    use crate::fault::get_fault_by_name;
+
+    fn racy() {
+        crate::yield_point!("interleave_test");
+    }
+
+    async fn racy_async() {
+        crate::yield_point_async!("interleave_async_test");
+    }
+
+    #[test]
+    fn forced_yield_points_perturb() {
+        let _lock = crate::fault::lock_catalog();
+        let _quiet = crate::fault::only_enable(&[]);
+        assert!(yield_points().any(|point| point.name() == "interleave_test"));
+
+        let point = get_fault_by_name("interleave_test").unwrap();
+        assert_eq!(Perturbation::choose(point), Perturbation::Continue);
+        let _forced = point.force_scoped(1);
+        assert!(Perturbation::choose(point).is_perturbed());
+
+        let _forced = point.force_scoped(1);
+        racy();
+        assert_eq!(point.count_pending(), 0);
+
+        let point = get_fault_by_name("interleave_async_test").unwrap();
+        let _forced = point.force_scoped(1);
+        crate::timer::tests::block_on(racy_async());
+        assert_eq!(point.count_pending(), 0);
+    }
+}
@@ -0,0 +1,455 @@
+//! Fault-injecting wrappers for [`std::io::Read`] and [`std::io::Write`].
+//!
+//! [`FaultyReader`] and [`FaultyWriter`] register a set of faults named after
+//! the label given to each instance, for example `"wal.short_write"` for a
+//! writer labeled `"wal"`. Instances sharing a label share their faults, so
+//! faults can be disabled or forced per label via
+//! [`get_fault_by_name`](crate::fault::get_fault_by_name).
+//!
+//! The injected failures are ones that well-behaved readers and writers are
+//! allowed to produce: short reads and writes, [`ErrorKind::Interrupted`],
+//! [`ErrorKind::UnexpectedEof`], flush failures and bit flips.
+//! [`ErrorKind::WouldBlock`] is only valid for non-blocking I/O, so its fault
+//! starts out disabled.
+//!
+//! With the `tokio` feature both wrappers also implement tokio's `AsyncRead`
+//! and `AsyncWrite`. Async operations inject the same failures, except that
+//! [`ErrorKind::WouldBlock`] is replaced by spurious [`Poll::Pending`] results
+//! which immediately re-wake the task, and additionally delay readiness for
+//! up to 10ms.
+//!
+//! When the `enabled` feature is off both wrappers are transparent
+//! passthroughs.
+//!
+//! [`Poll::Pending`]: std::task::Poll::Pending
+//!
+//! ```
+//! use std::io::{Read, Write};
+//! use precept::io::{FaultyReader, FaultyWriter};
+//!
+//! let mut writer = FaultyWriter::new("example", Vec::new());
+//! let _ = writer.write_all(b"hello");
+//!
+//! let mut reader = FaultyReader::new("example", &b"hello"[..]);
+//! let mut buf = vec![];
+//! let _ = reader.read_to_end(&mut buf);
+//! ```
+//!
+//! [`ErrorKind::Interrupted`]: std::io::ErrorKind::Interrupted
+//! [`ErrorKind::UnexpectedEof`]: std::io::ErrorKind::UnexpectedEof
+//! [`ErrorKind::WouldBlock`]: std::io::ErrorKind::WouldBlock
+//! [`Poll::Pending`]: std::task::Poll::Pending
+
+use std::io::{self, Read, Write};
+
+#[cfg(feature = "enabled")]
+use std::{borrow::Cow, io::ErrorKind};
+
+#[cfg(feature = "enabled")]
+use crate::fault::{FaultEntry, register_with};
+
+#[cfg(feature = "tokio")]
+mod async_io;
+
+/// Trips `fault`, emitting a `precept_fault` event naming the wrapper label
+/// when it trips.
+#[cfg(feature = "enabled")]
+#[track_caller]
+pub(crate) fn trip(fault: &'static FaultEntry, label: &str) -> bool {
+    let tripped = fault.trip();
+    if tripped {
+        crate::emit_event!("precept_fault", { "name": fault.name(), "details": { "label": label } });
+    }
+    tripped
+}
+
+/// Returns a random length in `1..len` drawn for `fault`, or `len` if `len`
+/// is less than 2.
+#[cfg(feature = "enabled")]
+pub(crate) fn short_len(fault: &FaultEntry, len: usize) -> usize {
+    if len < 2 {
+        len
+    } else {
+        crate::random::range_for(fault.name(), 1..len as u64) as usize
+    }
+}
+
+/// Returns the index of a random bit among `len` bytes drawn for `fault`, or
+/// `None` if `len` is zero.
+#[cfg(feature = "enabled")]
+fn random_bit(fault: &FaultEntry, len: usize) -> Option<u64> {
+    (len > 0).then(|| crate::random::range_for(fault.name(), 0..len as u64 * 8))
+}
+
+/// Flips a random bit in `buf`, drawn for `fault`.
+#[cfg(feature = "enabled")]
+pub(crate) fn flip_bit(fault: &FaultEntry, buf: &mut [u8]) {
+    if let Some(bit) = random_bit(fault, buf.len()) {
+        buf[(bit / 8) as usize] ^= 1 << (bit % 8);
+    }
+}
+
+/// How write faults alter the caller's buffer.
+#[cfg(feature = "enabled")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Corruption {
+    /// The number of bytes to keep, if the write is truncated.
+    truncate: Option<usize>,
+    /// The index of the bit to flip among the kept bytes, if any.
+    bit: Option<u64>,
+}
+
+#[cfg(feature = "enabled")]
+impl Corruption {
+    /// Returns the bytes to write in place of `buf`. A decision made for a
+    /// longer buffer is clamped to the length of `buf`.
+    fn apply(self, buf: &[u8]) -> Cow<'_, [u8]> {
+        let buf = &buf[..self.truncate.unwrap_or(buf.len()).min(buf.len())];
+        match self.bit {
+            Some(bit) if bit < buf.len() as u64 * 8 => {
+                let mut corrupt = buf.to_vec();
+                corrupt[(bit / 8) as usize] ^= 1 << (bit % 8);
+                Cow::Owned(corrupt)
+            }
+            _ => Cow::Borrowed(buf),
+        }
+    }
+}
+
+#[cfg(feature = "enabled")]
+#[derive(Debug, Clone)]
+struct ReadFaults {
+    label: String,
+    interrupted: &'static FaultEntry,
+    would_block: &'static FaultEntry,
+    unexpected_eof: &'static FaultEntry,
+    short_read: &'static FaultEntry,
+    bit_flip: &'static FaultEntry,
+    #[cfg(feature = "tokio")]
+    readiness: async_io::Readiness,
+}
+
+#[cfg(feature = "enabled")]
+impl ReadFaults {
+    fn new(label: &str) -> Self {
+        Self {
+            label: label.to_owned(),
+            interrupted: register_with(&format!("{label}.read_interrupted"), true),
+            would_block: register_with(&format!("{label}.read_would_block"), false),
+            unexpected_eof: register_with(&format!("{label}.unexpected_eof"), true),
+            short_read: register_with(&format!("{label}.short_read"), true),
+            bit_flip: register_with(&format!("{label}.read_bit_flip"), true),
+            #[cfg(feature = "tokio")]
+            readiness: async_io::Readiness::new(label, "read"),
+        }
+    }
+
+    /// Returns an error to inject in place of a read, if any. Async reads
+    /// inject spurious pending results instead of [`ErrorKind::WouldBlock`].
+    #[track_caller]
+    fn error(&self, would_block: bool) -> Option<io::Error> {
+        let kind = if trip(self.interrupted, &self.label) {
+            ErrorKind::Interrupted
+        } else if would_block && trip(self.would_block, &self.label) {
+            ErrorKind::WouldBlock
+        } else if trip(self.unexpected_eof, &self.label) {
+            ErrorKind::UnexpectedEof
+        } else {
+            return None;
+        };
+        Some(io::Error::new(kind, "precept injected fault"))
+    }
+
+    /// Returns how many of `len` requested bytes to read.
+    #[track_caller]
+    fn read_len(&self, len: usize) -> usize {
+        if trip(self.short_read, &self.label) {
+            short_len(self.short_read, len)
+        } else {
+            len
+        }
+    }
+
+    /// Possibly flips a bit in freshly read bytes.
+    #[track_caller]
+    fn corrupt(&self, buf: &mut [u8]) {
+        if trip(self.bit_flip, &self.label) {
+            flip_bit(self.bit_flip, buf);
+        }
+    }
+}
+
+/// A [`Read`] adapter which injects faults into reads.
+///
+/// See the [module docs](self) for the injected failures.
+#[derive(Debug, Clone)]
+pub struct FaultyReader<R> {
+    inner: R,
+    #[cfg(feature = "enabled")]
+    faults: ReadFaults,
+}
+
+impl<R> FaultyReader<R> {
+    /// Wraps `inner`, registering its faults under `label`.
+    #[cfg_attr(not(feature = "enabled"), allow(unused_variables))]
+    pub fn new(label: &str, inner: R) -> Self {
+        Self {
+            inner,
+            #[cfg(feature = "enabled")]
+            faults: ReadFaults::new(label),
+        }
+    }
+
+    /// Returns a reference to the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this adapter, returning the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for FaultyReader<R> {
+    #[cfg(not(feature = "enabled"))]
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+
+    #[cfg(feature = "enabled")]
+    #[track_caller]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(err) = self.faults.error(true) {
+            return Err(err);
+        }
+        let len = self.faults.read_len(buf.len());
+        let n = self.inner.read(&mut buf[..len])?;
+        self.faults.corrupt(&mut buf[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "enabled")]
+#[derive(Debug, Clone)]
+struct WriteFaults {
+    label: String,
+    interrupted: &'static FaultEntry,
+    would_block: &'static FaultEntry,
+    short_write: &'static FaultEntry,
+    bit_flip: &'static FaultEntry,
+    flush: &'static FaultEntry,
+    #[cfg(feature = "tokio")]
+    readiness: async_io::Readiness,
+}
+
+#[cfg(feature = "enabled")]
+impl WriteFaults {
+    fn new(label: &str) -> Self {
+        Self {
+            label: label.to_owned(),
+            interrupted: register_with(&format!("{label}.write_interrupted"), true),
+            would_block: register_with(&format!("{label}.write_would_block"), false),
+            short_write: register_with(&format!("{label}.short_write"), true),
+            bit_flip: register_with(&format!("{label}.write_bit_flip"), true),
+            flush: register_with(&format!("{label}.flush"), true),
+            #[cfg(feature = "tokio")]
+            readiness: async_io::Readiness::new(label, "write"),
+        }
+    }
+
+    /// Returns an error to inject in place of a write, if any. Async writes
+    /// inject spurious pending results instead of [`ErrorKind::WouldBlock`].
+    #[track_caller]
+    fn error(&self, would_block: bool) -> Option<io::Error> {
+        let kind = if trip(self.interrupted, &self.label) {
+            ErrorKind::Interrupted
+        } else if would_block && trip(self.would_block, &self.label) {
+            ErrorKind::WouldBlock
+        } else {
+            return None;
+        };
+        Some(io::Error::new(kind, "precept injected fault"))
+    }
+
+    /// Returns the bytes to write in place of `buf`, which may be truncated
+    /// and have a bit flipped.
+    #[track_caller]
+    fn corrupt<'a>(&self, buf: &'a [u8]) -> Cow<'a, [u8]> {
+        self.corruption(buf.len()).apply(buf)
+    }
+
+    /// Decides how to corrupt a write of `len` bytes.
+    #[track_caller]
+    fn corruption(&self, len: usize) -> Corruption {
+        let truncate =
+            trip(self.short_write, &self.label).then(|| short_len(self.short_write, len));
+        let bit = if trip(self.bit_flip, &self.label) {
+            random_bit(self.bit_flip, truncate.unwrap_or(len))
+        } else {
+            None
+        };
+        Corruption { truncate, bit }
+    }
+
+    /// Returns an error to inject in place of a flush, if any.
+    #[track_caller]
+    fn flush_error(&self) -> Option<io::Error> {
+        trip(self.flush, &self.label).then(|| io::Error::other("precept injected flush failure"))
+    }
+}
+
+/// A [`Write`] adapter which injects faults into writes and flushes.
+///
+/// See the [module docs](self) for the injected failures.
+#[derive(Debug, Clone)]
+pub struct FaultyWriter<W> {
+    inner: W,
+    #[cfg(feature = "enabled")]
+    faults: WriteFaults,
+}
+
+impl<W> FaultyWriter<W> {
+    /// Wraps `inner`, registering its faults under `label`.
+    #[cfg_attr(not(feature = "enabled"), allow(unused_variables))]
+    pub fn new(label: &str, inner: W) -> Self {
+        Self {
+            inner,
+            #[cfg(feature = "enabled")]
+            faults: WriteFaults::new(label),
+        }
+    }
+
+    /// Returns a reference to the wrapped writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Unwraps this adapter, returning the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for FaultyWriter<W> {
+    #[cfg(not(feature = "enabled"))]
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    #[cfg(feature = "enabled")]
+    #[track_caller]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(err) = self.faults.error(true) {
+            return Err(err);
+        }
+        let buf = self.faults.corrupt(buf);
+        self.inner.write(&buf)
+    }
+
+    #[cfg(not(feature = "enabled"))]
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    #[cfg(feature = "enabled")]
+    #[track_caller]
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(err) = self.faults.flush_error() {
+            return Err(err);
+        }
+        self.inner.flush()
+    }
+}
+
+#[cfg(all(test, feature = "enabled"))]
+mod tests {
+    use std::io::{ErrorKind, Read, Write};
+
+    use super::{FaultyReader, FaultyWriter};
+    use crate::fault::{FaultGuard, get_fault_by_name};
+
+    /// Disables every fault registered under `label`.
+    fn quiet(label: &str) -> Vec<FaultGuard<'static>> {
+        crate::fault::all_faults()
+            .filter(|f| f.name().starts_with(&format!("{label}.")))
+            .map(|f| f.disable_scoped())
+            .collect()
+    }
+
+    #[test]
+    fn reader_faults() {
+        let _lock = crate::fault::lock_catalog();
+        let mut reader = FaultyReader::new("io_test_reader", &[0u8; 8][..]);
+        let _quiet = quiet("io_test_reader");
+        let mut buf = [0u8; 8];
+
+        let _forced = get_fault_by_name("io_test_reader.read_interrupted")
+            .unwrap()
+            .force_scoped(1);
+        let line = line!() + 1;
+        let err = reader.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Interrupted);
+        // decisions are recorded at the caller of the wrapper
+        let decisions = crate::fault::recent_decisions();
+        let decision = decisions
+            .iter()
+            .rev()
+            .find(|d| d.name() == "io_test_reader.read_interrupted")
+            .unwrap();
+        assert_eq!(decision.location().file(), file!());
+        assert_eq!(decision.location().line(), line);
+
+        let _forced = get_fault_by_name("io_test_reader.short_read")
+            .unwrap()
+            .force_scoped(1);
+        let n = reader.read(&mut buf).unwrap();
+        assert!((1..8).contains(&n));
+
+        let _forced = get_fault_by_name("io_test_reader.read_bit_flip")
+            .unwrap()
+            .force_scoped(1);
+        let n = reader.read(&mut buf).unwrap();
+        assert_eq!(buf[..n].iter().map(|b| b.count_ones()).sum::<u32>(), 1);
+    }
+
+    #[test]
+    fn writer_faults() {
+        let _lock = crate::fault::lock_catalog();
+        let mut writer = FaultyWriter::new("io_test_writer", Vec::new());
+        let _quiet = quiet("io_test_writer");
+
+        let _forced = get_fault_by_name("io_test_writer.short_write")
+            .unwrap()
+            .force_scoped(1);
+        let n = writer.write(&[0u8; 8]).unwrap();
+        assert!((1..8).contains(&n));
+
+        let _forced = get_fault_by_name("io_test_writer.flush")
+            .unwrap()
+            .force_scoped(1);
+        assert!(writer.flush().is_err());
+        writer.flush().unwrap();
+
+        let _forced = get_fault_by_name("io_test_writer.write_bit_flip")
+            .unwrap()
+            .force_scoped(1);
+        writer.get_mut().clear();
+        writer.write_all(&[0u8; 8]).unwrap();
+        let ones: u32 = writer.get_ref().iter().map(|b| b.count_ones()).sum();
+        assert_eq!(ones, 1);
+    }
+}
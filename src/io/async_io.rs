@@ -0,0 +1,380 @@
+//! tokio `AsyncRead` and `AsyncWrite` implementations for the io fault
+//! wrappers.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::{FaultyReader, FaultyWriter};
+
+#[cfg(feature = "enabled")]
+use std::{task::ready, time::Duration, time::Instant};
+
+#[cfg(feature = "enabled")]
+use super::Corruption;
+
+#[cfg(feature = "enabled")]
+use crate::fault::{FaultEntry, LatencyDistribution, register_with};
+
+/// The maximum delay injected before an async operation becomes ready.
+#[cfg(feature = "enabled")]
+const MAX_DELAY: Duration = Duration::from_millis(10);
+
+/// Injects spurious pending results and delayed readiness into an async
+/// operation.
+#[cfg(feature = "enabled")]
+#[derive(Debug, Clone)]
+pub(super) struct Readiness {
+    pending: &'static FaultEntry,
+    delay: &'static FaultEntry,
+    deadline: Option<Instant>,
+    /// The most bytes a read may return, kept while its inner poll is
+    /// pending.
+    read: Option<usize>,
+    /// How a write corrupts the buffer it is polled with, kept while its
+    /// inner poll is pending.
+    write: Option<Corruption>,
+    /// Whether a flush passed its fault checks and its inner poll is pending.
+    flush: bool,
+}
+
+#[cfg(feature = "enabled")]
+impl Readiness {
+    pub(super) fn new(label: &str, op: &str) -> Self {
+        Self {
+            pending: register_with(&format!("{label}.{op}_pending"), true),
+            delay: register_with(&format!("{label}.{op}_delay"), true),
+            deadline: None,
+            read: None,
+            write: None,
+            flush: false,
+        }
+    }
+
+    /// Returns [`Poll::Pending`] if the operation should not proceed yet,
+    /// arranging for the task to be woken again.
+    fn poll(&mut self, label: &str, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(deadline) = self.deadline {
+            if Instant::now() < deadline {
+                crate::timer::wake_at(deadline, cx.waker().clone());
+                return Poll::Pending;
+            }
+            // let the delayed operation proceed without another roll
+            self.deadline = None;
+            return Poll::Ready(());
+        }
+        if super::trip(self.pending, label) {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        if let Some(delay) = self
+            .delay
+            .trip_latency(MAX_DELAY, LatencyDistribution::default())
+        {
+            crate::emit_event!("precept_fault", {
+                "name": self.delay.name(),
+                "details": {
+                    "label": label,
+                    "delay_us": u64::try_from(delay.as_micros()).unwrap_or(u64::MAX),
+                },
+            });
+            let deadline = Instant::now() + delay;
+            self.deadline = Some(deadline);
+            crate::timer::wake_at(deadline, cx.waker().clone());
+            return Poll::Pending;
+        }
+        Poll::Ready(())
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for FaultyReader<R> {
+    #[cfg(not(feature = "enabled"))]
+    #[inline]
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+
+    #[cfg(feature = "enabled")]
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let faults = &mut this.faults;
+        let remaining = buf.remaining();
+        let len = match faults.readiness.read.take() {
+            Some(len) => len.min(remaining),
+            None => {
+                ready!(faults.readiness.poll(&faults.label, cx));
+                if let Some(err) = faults.error(false) {
+                    return Poll::Ready(Err(err));
+                }
+                faults.read_len(remaining)
+            }
+        };
+        let result = if len < remaining {
+            let mut scratch = vec![0; len];
+            let mut short = ReadBuf::new(&mut scratch);
+            let result = Pin::new(&mut this.inner).poll_read(cx, &mut short);
+            if let Poll::Ready(Ok(())) = result {
+                faults.corrupt(short.filled_mut());
+                buf.put_slice(short.filled());
+            }
+            result
+        } else {
+            let start = buf.filled().len();
+            let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+            if let Poll::Ready(Ok(())) = result {
+                faults.corrupt(&mut buf.filled_mut()[start..]);
+            }
+            result
+        };
+        if result.is_pending() {
+            faults.readiness.read = Some(len);
+        }
+        result
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for FaultyWriter<W> {
+    #[cfg(not(feature = "enabled"))]
+    #[inline]
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    #[cfg(feature = "enabled")]
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let faults = &mut this.faults;
+        let corruption = match faults.readiness.write.take() {
+            Some(corruption) => corruption,
+            None => {
+                ready!(faults.readiness.poll(&faults.label, cx));
+                if let Some(err) = faults.error(false) {
+                    return Poll::Ready(Err(err));
+                }
+                faults.corruption(buf.len())
+            }
+        };
+        // the bytes are rebuilt from each poll's buffer, which may differ
+        // from the one the decision was made for
+        let result = Pin::new(&mut this.inner).poll_write(cx, &corruption.apply(buf));
+        if result.is_pending() {
+            faults.readiness.write = Some(corruption);
+        }
+        result
+    }
+
+    #[cfg(not(feature = "enabled"))]
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    #[cfg(feature = "enabled")]
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let faults = &mut this.faults;
+        if !std::mem::take(&mut faults.readiness.flush) {
+            ready!(faults.readiness.poll(&faults.label, cx));
+            if let Some(err) = faults.flush_error() {
+                return Poll::Ready(Err(err));
+            }
+        }
+        let result = Pin::new(&mut this.inner).poll_flush(cx);
+        if result.is_pending() {
+            faults.readiness.flush = true;
+        }
+        result
+    }
+
+    #[inline]
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(all(test, feature = "enabled"))]
+mod tests {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+    use crate::{
+        fault::{self, get_fault_by_name},
+        io::{FaultyReader, FaultyWriter},
+        timer::tests::block_on,
+    };
+
+    /// Wraps an io object so that its first poll returns [`Poll::Pending`].
+    struct PendingOnce<T> {
+        inner: T,
+        polled: bool,
+    }
+
+    impl<T> PendingOnce<T> {
+        fn new(inner: T) -> Self {
+            Self { inner, polled: false }
+        }
+
+        fn poll_once<R>(&mut self, poll: impl FnOnce(&mut T) -> Poll<R>) -> Poll<R> {
+            if std::mem::replace(&mut self.polled, true) {
+                poll(&mut self.inner)
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    impl<R: AsyncRead + Unpin> AsyncRead for PendingOnce<R> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            self.get_mut()
+                .poll_once(|inner| Pin::new(inner).poll_read(cx, buf))
+        }
+    }
+
+    impl<W: AsyncWrite + Unpin> AsyncWrite for PendingOnce<W> {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            self.get_mut()
+                .poll_once(|inner| Pin::new(inner).poll_write(cx, buf))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+        }
+    }
+
+    #[test]
+    fn pending_operations_keep_their_faults() {
+        let _lock = fault::lock_catalog();
+        let label = "async_io_test_pending";
+        let mut reader = FaultyReader::new(label, PendingOnce::new(&[0u8; 8][..]));
+        let mut writer = FaultyWriter::new(label, PendingOnce::new(Vec::new()));
+        let _quiet = fault::only_enable(&[]);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let short_read = get_fault_by_name(&format!("{label}.short_read")).unwrap();
+        let _forced = short_read.force_scoped(1);
+        let mut storage = [0u8; 8];
+        let mut buf = ReadBuf::new(&mut storage);
+        assert!(
+            Pin::new(&mut reader)
+                .poll_read(&mut cx, &mut buf)
+                .is_pending()
+        );
+        assert!(
+            Pin::new(&mut reader)
+                .poll_read(&mut cx, &mut buf)
+                .is_ready()
+        );
+        assert!((1..8).contains(&buf.filled().len()));
+
+        let short_write = get_fault_by_name(&format!("{label}.short_write")).unwrap();
+        let _forced = short_write.force_scoped(1);
+        assert!(
+            Pin::new(&mut writer)
+                .poll_write(&mut cx, &[0u8; 8])
+                .is_pending()
+        );
+        // a flush in between keeps the write's decision, and the bytes come
+        // from the buffer the write is polled with now
+        assert!(Pin::new(&mut writer).poll_flush(&mut cx).is_ready());
+        let poll = Pin::new(&mut writer).poll_write(&mut cx, &[1u8; 8]);
+        let Poll::Ready(Ok(n)) = poll else {
+            panic!("unexpected poll result {poll:?}")
+        };
+        assert!((1..8).contains(&n));
+        assert_eq!(writer.get_ref().inner, vec![1u8; n]);
+    }
+
+    #[test]
+    fn async_reader_faults() {
+        let _lock = fault::lock_catalog();
+        let mut reader = FaultyReader::new("async_io_test_reader", &[0u8; 8][..]);
+        let _quiet = fault::only_enable(&[]);
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut storage = [0u8; 8];
+
+        let _forced = get_fault_by_name("async_io_test_reader.read_pending")
+            .unwrap()
+            .force_scoped(1);
+        let mut buf = ReadBuf::new(&mut storage);
+        let poll = Pin::new(&mut reader).poll_read(&mut cx, &mut buf);
+        assert!(poll.is_pending());
+        let poll = Pin::new(&mut reader).poll_read(&mut cx, &mut buf);
+        assert!(matches!(poll, Poll::Ready(Ok(()))));
+        assert_eq!(buf.filled().len(), 8);
+
+        let mut reader = FaultyReader::new("async_io_test_reader", &[0u8; 8][..]);
+        let _forced = get_fault_by_name("async_io_test_reader.read_delay")
+            .unwrap()
+            .force_scoped(1);
+        let _short = get_fault_by_name("async_io_test_reader.short_read")
+            .unwrap()
+            .force_scoped(1);
+        let n = block_on(reader.read(&mut storage)).unwrap();
+        assert!((1..8).contains(&n));
+    }
+
+    #[test]
+    fn async_writer_faults() {
+        let _lock = fault::lock_catalog();
+        let mut writer = FaultyWriter::new("async_io_test_writer", Vec::new());
+        let _quiet = fault::only_enable(&[]);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let _forced = get_fault_by_name("async_io_test_writer.short_write")
+            .unwrap()
+            .force_scoped(1);
+        let poll = Pin::new(&mut writer).poll_write(&mut cx, &[0u8; 8]);
+        let Poll::Ready(Ok(n)) = poll else {
+            panic!("unexpected poll result {poll:?}")
+        };
+        assert!((1..8).contains(&n));
+
+        let _forced = get_fault_by_name("async_io_test_writer.flush")
+            .unwrap()
+            .force_scoped(1);
+        assert!(block_on(writer.flush()).is_err());
+        block_on(writer.flush()).unwrap();
+
+        let _forced = get_fault_by_name("async_io_test_writer.write_delay")
+            .unwrap()
+            .force_scoped(1);
+        block_on(writer.write_all(&[1u8; 4])).unwrap();
+        assert_eq!(writer.get_ref()[n..], [1u8; 4]);
+    }
+}
@@ -1,7 +1,18 @@
+pub mod alloc;
+pub mod collections;
+pub mod crash;
 pub mod dispatch;
 pub mod fault;
+pub mod fs;
 pub mod ghost;
+pub mod interleave;
+pub mod io;
+pub mod net;
 pub mod random;
+pub mod sim;
+pub mod sync;
+pub mod task;
+pub mod time;
 
 #[doc(inline)]
 pub use crate::ghost::GhostState;
@@ -12,8 +23,13 @@ pub mod catalog;
 #[doc(hidden)]
 pub mod function_name;
 
+#[doc(hidden)]
+pub mod timer;
+
 #[doc(hidden)]
 pub mod deps {
+    #[cfg(feature = "getrandom")]
+    pub use getrandom;
     pub use linkme;
     pub use serde_json;
 }
@@ -34,12 +50,21 @@ pub const ENABLED: bool = cfg!(feature = "enabled");
 /// This function sets up the global dispatcher, registers all catalog entries,
 /// and initializes faults. It should be called once at application startup.
 ///
+/// If the [`PRECEPT_FAULT_SWARM`](fault::SWARM_ENV) environment variable is set
+/// to `1`, a random subset of faults is enabled via [`fault::swarm`]. If the
+/// process was started by a [`crash::CrashHarness`], the chosen crash point is
+/// armed.
+///
 /// Returns an error if a dispatcher has already been set.
 pub fn init(dispatcher: &'static dyn Dispatch) -> Result<(), SetDispatchError> {
     if cfg!(feature = "enabled") {
         dispatch::set_dispatcher(dispatcher)?;
+        // seed the substreams before anything else draws from the dispatcher
+        random::seed_substreams(dispatcher.random());
         catalog::init_catalog();
         fault::init_faults();
+        fault::init_swarm();
+        crash::init_crash_point();
     }
     Ok(())
 }
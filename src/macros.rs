@@ -140,14 +140,59 @@ macro_rules! define_fault {
         use $crate::fault::FaultEntry;
         #[$crate::deps::linkme::distributed_slice($crate::fault::FAULT_CATALOG)]
         #[linkme(crate = $crate::deps::linkme)]
-        static FAULT: FaultEntry = FaultEntry::new($id);
+        static FAULT: FaultEntry =
+            FaultEntry::new($id).with_property(concat!("precept fault: ", $id));
         &FAULT
     }};
 }
 
+#[doc(hidden)]
+#[macro_export]
+macro_rules! trip_fault {
+    ($fault:expr, $property:expr) => {
+        $crate::trip_fault!($fault, $property, null)
+    };
+
+    ($fault:expr, $property:expr, $($details:tt)+) => {{
+        let fault: &'static $crate::fault::FaultEntry = $fault;
+        let tripped = fault.trip();
+        $crate::expect_sometimes!(tripped, $property, $($details)+);
+        if tripped {
+            $crate::emit_event!("precept_fault", { "name": fault.name(), "details": $($details)+ });
+        }
+        tripped
+    }};
+}
+
+/// Declares a named fault that can be shared between fault points and tests.
+///
+/// This expands to a `&'static FaultEntry` constant which may be passed to
+/// [`sometimes_fault!`](crate::sometimes_fault) in place of a fault name. Referring to the fault by
+/// constant rather than by string turns typos and renames into compile errors.
+///
+/// # Example
+/// ```
+/// precept::declare_fault!(pub DISK_FULL = "disk_full");
+///
+/// precept::sometimes_fault!(DISK_FULL, println!("the disk is full"));
+///
+/// // tests can reach the same fault without a string lookup
+/// let _guard = DISK_FULL.disable_scoped();
+/// ```
+#[macro_export]
+macro_rules! declare_fault {
+    ($($(#[$attr:meta])* $vis:vis $ident:ident = $name:literal);+ $(;)?) => {$(
+        $(#[$attr])*
+        $vis const $ident: &'static $crate::fault::FaultEntry = $crate::define_fault!($name);
+    )+};
+}
+
 /// Register a fault point. This fault will trigger 50% of the time when it is
-/// enabled or when it is explicitely scheduled to trigger via
-/// `FaultEntry::set_pending`.
+/// enabled and setup is complete, or when it is explicitely scheduled to
+/// trigger via `FaultEntry::set_pending`.
+///
+/// The fault may be named either with a string literal or with a constant
+/// declared by [`declare_fault!`].
 ///
 /// # Example
 /// ```
@@ -159,25 +204,374 @@ macro_rules! define_fault {
 /// ```
 #[macro_export]
 macro_rules! sometimes_fault {
-    ($name:expr, $fault:expr) => {
-        $crate::sometimes_fault!($name, $fault, null);
+    ($handle:path, $fault:expr $(, $($details:tt)+)?) => {{
+        if $crate::trip_fault!($handle, $handle.property() $(, $($details)+)?) {
+            $fault
+        }
+    }};
+
+    ($name:expr, $fault:expr $(, $($details:tt)+)?) => {{
+        let fault = $crate::define_fault!($name);
+        if $crate::trip_fault!(fault, concat!("precept fault: ", $name) $(, $($details)+)?) {
+            $fault
+        }
+    }};
+}
+
+/// Register a fault point which evaluates to `Err` when the fault trips.
+///
+/// Evaluates to a `Result<(), E>`: `Err(make_err())` when the fault trips and
+/// `Ok(())` otherwise, so it composes with `?`. The fault is registered and
+/// reported exactly like [`sometimes_fault!`](crate::sometimes_fault), and may likewise be named with a
+/// string literal or a constant declared by [`declare_fault!`].
+///
+/// # Example
+/// ```
+/// use std::io;
+///
+/// fn write_block() -> io::Result<()> {
+///     precept::fault_result!("disk_full", || io::Error::from(io::ErrorKind::StorageFull))?;
+///     Ok(())
+/// }
+/// # let _ = write_block();
+/// ```
+#[macro_export]
+macro_rules! fault_result {
+    ($handle:path, $make_err:expr $(, $($details:tt)+)?) => {{
+        if $crate::trip_fault!($handle, $handle.property() $(, $($details)+)?) {
+            ::core::result::Result::Err(($make_err)())
+        } else {
+            ::core::result::Result::Ok(())
+        }
+    }};
+
+    ($name:expr, $make_err:expr $(, $($details:tt)+)?) => {{
+        let fault = $crate::define_fault!($name);
+        if $crate::trip_fault!(fault, concat!("precept fault: ", $name) $(, $($details)+)?) {
+            ::core::result::Result::Err(($make_err)())
+        } else {
+            ::core::result::Result::Ok(())
+        }
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! trip_latency_fault {
+    ($fault:expr, $property:expr, $max:expr, $distribution:expr) => {
+        $crate::trip_latency_fault!($fault, $property, $max, $distribution, null)
+    };
+
+    ($fault:expr, $property:expr, $max:expr, $distribution:expr, $($details:tt)+) => {{
+        let fault: &'static $crate::fault::FaultEntry = $fault;
+        let delay = fault.trip_latency($max, $distribution);
+        $crate::expect_sometimes!(delay.is_some(), $property, $($details)+);
+        if let Some(delay) = delay {
+            $crate::emit_event!("precept_fault", {
+                "name": fault.name(),
+                "delay_us": u64::try_from(delay.as_micros()).unwrap_or(u64::MAX),
+                "details": $($details)+
+            });
+        }
+        delay
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! latency_fault_delay {
+    ($handle:path, max = $max:expr, distribution = $distribution:expr $(, $($details:tt)+)?) => {
+        $crate::trip_latency_fault!(
+            $handle,
+            $handle.property(),
+            $max,
+            $distribution
+            $(, $($details)+)?
+        )
+    };
+
+    ($handle:path, max = $max:expr $(, $($details:tt)+)?) => {
+        $crate::trip_latency_fault!(
+            $handle,
+            $handle.property(),
+            $max,
+            $crate::fault::LatencyDistribution::Uniform
+            $(, $($details)+)?
+        )
     };
 
-    ($name:expr, $fault:expr, $($details:tt)+) => {{
+    ($name:expr, max = $max:expr, distribution = $distribution:expr $(, $($details:tt)+)?) => {{
         let fault = $crate::define_fault!($name);
-        let tripped = fault.trip();
-        $crate::expect_sometimes!(
-            tripped,
+        $crate::trip_latency_fault!(
+            fault,
             concat!("precept fault: ", $name),
-            $($details)+
-        );
-        if tripped {
-            $crate::emit_event!("precept_fault", { "name": $name, "details": $($details)+ });
-            $fault
+            $max,
+            $distribution
+            $(, $($details)+)?
+        )
+    }};
+
+    ($name:expr, max = $max:expr $(, $($details:tt)+)?) => {{
+        let fault = $crate::define_fault!($name);
+        $crate::trip_latency_fault!(
+            fault,
+            concat!("precept fault: ", $name),
+            $max,
+            $crate::fault::LatencyDistribution::Uniform
+            $(, $($details)+)?
+        )
+    }};
+}
+
+/// Register a latency fault point, which blocks the current thread for a
+/// random delay when it trips.
+///
+/// The delay is at most `max` and is drawn from the dispatcher using an
+/// optional [`LatencyDistribution`](crate::fault::LatencyDistribution), which
+/// defaults to uniform. Each delay is reported in a `precept_fault` event.
+/// Latency faults are enabled, disabled and forced exactly like
+/// [`sometimes_fault!`](crate::sometimes_fault), and may likewise be named with a constant declared by
+/// [`declare_fault!`].
+///
+/// Use [`latency_fault_async!`](crate::latency_fault_async) from async code.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use precept::fault::LatencyDistribution;
+///
+/// precept::latency_fault!("slow_disk", max = Duration::from_millis(2));
+/// precept::latency_fault!(
+///     "slow_network",
+///     max = Duration::from_millis(2),
+///     distribution = LatencyDistribution::Spike,
+///     { "optional": "details" }
+/// );
+/// ```
+#[macro_export]
+macro_rules! latency_fault {
+    ($($args:tt)+) => {
+        if let Some(delay) = $crate::latency_fault_delay!($($args)+) {
+            ::std::thread::sleep(delay);
         }
+    };
+}
+
+/// The async equivalent of [`latency_fault!`], which must be used within an
+/// async context.
+///
+/// The delay is driven by a runtime-agnostic timer, so it works with any
+/// executor.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+///
+/// async fn read_block() {
+///     precept::latency_fault_async!("slow_disk", max = Duration::from_millis(2));
+/// }
+/// ```
+#[macro_export]
+macro_rules! latency_fault_async {
+    ($($args:tt)+) => {
+        if let Some(delay) = $crate::latency_fault_delay!($($args)+) {
+            $crate::timer::sleep(delay).await;
+        }
+    };
+}
+
+/// Register a multi-way fault point which, when it trips, runs exactly one of
+/// several alternative failure behaviors.
+///
+/// Each alternative registers its own fault named `"<name>.<alternative>"`, so
+/// alternatives can be disabled or forced individually, and each reports a
+/// `Sometimes` expectation so guidance can see which alternatives have been
+/// explored. An alternative may be given a relative weight in brackets; the
+/// default weight is 1. See [`trip_choice`](crate::fault::trip_choice) for how
+/// the alternative is selected.
+///
+/// # Example
+/// ```
+/// precept::choose_fault!("write", {
+///     "short" => println!("short write"),
+///     "eio" [3] => println!("EIO"),
+///     "corrupt" => println!("corrupt the written data"),
+/// }, { "optional": "details" });
+/// ```
+#[macro_export]
+macro_rules! choose_fault {
+    (@weight) => { 1 };
+    (@weight $weight:expr) => { $weight };
+
+    ($name:expr, { $($alternative:literal $([$weight:expr])? => $fault:expr),+ $(,)? }) => {
+        $crate::choose_fault!($name, { $($alternative $([$weight])? => $fault),+ }, null)
+    };
+
+    ($name:expr, { $($alternative:literal $([$weight:expr])? => $fault:expr),+ $(,)? }, $($details:tt)+) => {{
+        let details = $crate::deps::serde_json::json!($($details)+);
+        let alternatives: &[(&'static $crate::fault::FaultEntry, u32)] = &[$((
+            $crate::define_fault!(concat!($name, ".", $alternative)),
+            $crate::choose_fault!(@weight $($weight)?),
+        )),+];
+        let chosen = $crate::fault::trip_choice(alternatives);
+        let mut remaining = alternatives.iter();
+        $(
+            if let Some(&(fault, _)) = remaining.next() {
+                let tripped = chosen.is_some_and(|chosen| ::std::ptr::eq(chosen, fault));
+                $crate::expect_sometimes!(
+                    tripped,
+                    concat!("precept fault: ", $name, ".", $alternative),
+                    details
+                );
+                if tripped {
+                    $crate::emit_event!("precept_fault", { "name": fault.name(), "details": details });
+                    $fault
+                }
+            }
+        )+
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! define_crash_point {
+    ($name:expr) => {{
+        use $crate::fault::FaultEntry;
+        #[$crate::deps::linkme::distributed_slice($crate::fault::FAULT_CATALOG)]
+        #[linkme(crate = $crate::deps::linkme)]
+        static FAULT: FaultEntry = FaultEntry::new($name)
+            .with_property(concat!("precept crash point: ", $name))
+            .with_enabled(false);
+        #[$crate::deps::linkme::distributed_slice($crate::crash::CRASH_POINTS)]
+        #[linkme(crate = $crate::deps::linkme)]
+        static POINT: &FaultEntry = &FAULT;
+        &FAULT
     }};
 }
 
+/// Register a crash point, which terminates the process when it trips.
+///
+/// Crash points are faults which start out disabled, so they only fire when
+/// enabled or forced via `FaultEntry::set_pending`, typically by a
+/// [`CrashHarness`](crate::crash::CrashHarness). See the [`crash`](crate::crash)
+/// module for details.
+///
+/// # Example
+/// ```
+/// fn append_to_wal(entry: &[u8]) {
+///     // write the entry ...
+///     precept::crash_point!("after_wal_append", { "len": entry.len() });
+///     // update the index ...
+/// }
+/// # append_to_wal(b"entry");
+/// ```
+#[macro_export]
+macro_rules! crash_point {
+    ($name:expr $(, $($details:tt)+)?) => {{
+        let point = $crate::define_crash_point!($name);
+        if $crate::trip_fault!(point, concat!("precept crash point: ", $name) $(, $($details)+)?) {
+            $crate::crash::crash(point);
+        }
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! define_yield_point {
+    ($name:expr) => {{
+        use $crate::fault::FaultEntry;
+        #[$crate::deps::linkme::distributed_slice($crate::fault::FAULT_CATALOG)]
+        #[linkme(crate = $crate::deps::linkme)]
+        static FAULT: FaultEntry =
+            FaultEntry::new($name).with_property(concat!("precept yield point: ", $name));
+        #[$crate::deps::linkme::distributed_slice($crate::interleave::YIELD_POINTS)]
+        #[linkme(crate = $crate::deps::linkme)]
+        static POINT: &FaultEntry = &FAULT;
+        let perturbation = $crate::interleave::Perturbation::choose(&FAULT);
+        $crate::expect_sometimes!(
+            perturbation.is_perturbed(),
+            concat!("precept yield point: ", $name)
+        );
+        perturbation
+    }};
+}
+
+/// Register a yield point, which randomly perturbs the schedule of the
+/// current thread to shake out race conditions.
+///
+/// See the [`interleave`](crate::interleave) module for the possible
+/// perturbations. Use [`yield_point_async!`](crate::yield_point_async) from async code.
+///
+/// # Example
+/// ```
+/// precept::yield_point!("after_lock_release");
+/// ```
+#[macro_export]
+macro_rules! yield_point {
+    ($name:expr) => {
+        $crate::define_yield_point!($name).apply()
+    };
+}
+
+/// The async equivalent of [`yield_point!`], which must be used within an
+/// async context.
+///
+/// Yielding returns control to the executor, and sleeping is driven by a
+/// runtime-agnostic timer, so it works with any executor.
+///
+/// # Example
+/// ```
+/// async fn send_ack() {
+///     precept::yield_point_async!("before_ack");
+/// }
+/// ```
+#[macro_export]
+macro_rules! yield_point_async {
+    ($name:expr) => {
+        $crate::define_yield_point!($name).apply_async().await
+    };
+}
+
+/// Routes every call to `getrandom` through the dispatcher, so that crates
+/// which draw OS entropy, such as `rand` and `uuid`, replay deterministically.
+///
+/// This defines a `getrandom` custom backend. Invoke it once, in the root
+/// crate of the program, and build with
+/// `RUSTFLAGS='--cfg getrandom_backend="custom"'`. Calls the dispatcher
+/// makes to `getrandom` itself, as the default dispatchers do through `rand`,
+/// draw from a generator seeded during [`init`](crate::init), so for replay
+/// seed the dispatcher with
+/// [`SeededDispatch`](crate::dispatch::seeded::SeededDispatch) or use a
+/// guidance system's dispatcher.
+///
+/// Requires the `getrandom` feature. When the `enabled` feature is off this
+/// expands to nothing, so the custom backend must only be selected in builds
+/// with `enabled`.
+///
+/// # Example
+/// ```
+/// precept::register_getrandom!();
+/// ```
+#[cfg(feature = "getrandom")]
+#[macro_export]
+macro_rules! register_getrandom {
+    () => {
+        #[unsafe(no_mangle)]
+        unsafe extern "Rust" fn __getrandom_v03_custom(
+            dest: *mut u8,
+            len: usize,
+        ) -> ::core::result::Result<(), $crate::deps::getrandom::Error> {
+            // SAFETY: getrandom passes a writable buffer of `len` bytes, which
+            // may be uninitialized until it is zeroed here.
+            let dest = unsafe {
+                ::core::ptr::write_bytes(dest, 0, len);
+                ::core::slice::from_raw_parts_mut(dest, len)
+            };
+            $crate::random::getrandom_backend(dest)
+        }
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{catalog::Expectation, fault};
@@ -231,6 +625,7 @@ mod tests {
 
     #[test]
     fn test_fault() {
+        let _lock = fault::lock_catalog();
         fault::init_faults();
 
         let fault = crate::fault::get_fault_by_name("disabled").unwrap();
@@ -240,9 +635,18 @@ mod tests {
         let fault = crate::fault::get_fault_by_name("forced").unwrap();
         fault.set_pending(1);
         let mut foo = false;
+        let line = line!() + 1;
         sometimes_fault!("forced", foo = true);
         assert!(foo);
         assert_eq!(fault.count_pending(), 0);
+        let decisions = fault::recent_decisions();
+        let decision = decisions
+            .iter()
+            .rev()
+            .find(|d| d.name() == "forced")
+            .unwrap();
+        assert_eq!(decision.location().file(), file!());
+        assert_eq!(decision.location().line(), line);
 
         let fault = crate::fault::get_fault_by_name("disabled_forced").unwrap();
         fault.disable();
@@ -252,4 +656,110 @@ mod tests {
         assert!(foo);
         assert_eq!(fault.count_pending(), 0);
     }
+
+    declare_fault! {
+        /// a fault shared by multiple fault points
+        TYPED = "typed";
+        pub(crate) TYPED_DISABLED = "typed_disabled";
+    }
+
+    #[test]
+    fn test_declared_fault() {
+        let _lock = fault::lock_catalog();
+        fault::init_faults();
+
+        let found = fault::get_fault_by_name("typed").unwrap();
+        assert!(std::ptr::eq(found, TYPED));
+        assert_eq!(TYPED.name(), "typed");
+
+        let _guard = TYPED.force_scoped(2);
+        let mut count = 0;
+        sometimes_fault!(TYPED, count += 1);
+        sometimes_fault!(TYPED, count += 1, { "key": 123 });
+        assert_eq!(count, 2);
+
+        let _guard = TYPED_DISABLED.disable_scoped();
+        sometimes_fault!(TYPED_DISABLED, unreachable!("disabled faults"));
+    }
+
+    #[test]
+    fn test_latency_fault() {
+        use std::time::{Duration, Instant};
+
+        use crate::fault::LatencyDistribution;
+
+        let _lock = fault::lock_catalog();
+        fault::init_faults();
+
+        let max = Duration::from_millis(5);
+        let _disabled = TYPED_DISABLED.disable_scoped();
+        latency_fault!(TYPED_DISABLED, max = Duration::from_secs(60));
+
+        let fault = fault::get_fault_by_name("latency_forced").unwrap();
+        let _disabled = fault.disable_scoped();
+        let _forced = fault.force_scoped(2);
+        let start = Instant::now();
+        for _ in 0..3 {
+            latency_fault!(
+                "latency_forced",
+                max = max,
+                distribution = LatencyDistribution::Exponential,
+                { "key": 123 }
+            );
+        }
+        assert_eq!(fault.count_pending(), 0);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let fault = fault::get_fault_by_name("latency_async").unwrap();
+        let _forced = fault.force_scoped(1);
+        crate::timer::tests::block_on(async {
+            latency_fault_async!("latency_async", max = max);
+        });
+        assert_eq!(fault.count_pending(), 0);
+    }
+
+    #[test]
+    fn test_choose_fault() {
+        let _lock = fault::lock_catalog();
+        fault::init_faults();
+
+        let short = fault::get_fault_by_name("choice.short").unwrap();
+        let eio = fault::get_fault_by_name("choice.eio").unwrap();
+        let _short = short.disable_scoped();
+        let _eio = eio.disable_scoped();
+        let _forced = eio.force_scoped(1);
+
+        let mut chosen = vec![];
+        for _ in 0..2 {
+            choose_fault!("choice", {
+                "short" => chosen.push("short"),
+                "eio" [2] => chosen.push("eio"),
+            }, { "key": 123 });
+        }
+        assert_eq!(chosen, ["eio"]);
+    }
+
+    #[test]
+    fn test_fault_result() {
+        fn write(fault: bool) -> Result<(), String> {
+            if fault {
+                fault_result!("result_forced", || String::from("injected"), { "key": 123 })?;
+            } else {
+                fault_result!(TYPED_DISABLED, || String::from("injected"))?;
+            }
+            Ok(())
+        }
+
+        let _lock = fault::lock_catalog();
+        fault::init_faults();
+
+        let fault = fault::get_fault_by_name("result_forced").unwrap();
+        let _disabled = fault.disable_scoped();
+        let _forced = fault.force_scoped(1);
+        assert_eq!(write(true), Err("injected".into()));
+        assert_eq!(write(true), Ok(()));
+
+        let _guard = TYPED_DISABLED.disable_scoped();
+        assert_eq!(write(false), Ok(()));
+    }
 }
@@ -137,6 +137,17 @@ macro_rules! expect_unreachable {
     };
 }
 
+#[macro_export]
+macro_rules! declare_fault {
+    ($($(#[$attr:meta])* $vis:vis $ident:ident = $name:literal);+ $(;)?) => {$(
+        $(#[$attr])*
+        $vis const $ident: &'static $crate::fault::FaultEntry = {
+            static FAULT: $crate::fault::FaultEntry = $crate::fault::FaultEntry::new($name);
+            &FAULT
+        };
+    )+};
+}
+
 #[macro_export]
 macro_rules! sometimes_fault {
     ($name:expr, $fault:expr) => {
@@ -153,3 +164,101 @@ macro_rules! sometimes_fault {
         }
     };
 }
+
+#[macro_export]
+macro_rules! choose_fault {
+    ($name:expr, { $($alternative:literal $([$weight:expr])? => $fault:expr),+ $(,)? } $(, $($details:tt)+)?) => {
+        if false {
+            let _ = &$name;
+            $(
+                $(let _: u32 = $weight;)?
+                let _ = $fault;
+            )+
+            $(let _ = $crate::deps::serde_json::json!($($details)+);)?
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! fault_result {
+    ($name:expr, $make_err:expr) => {{
+        if false {
+            let _ = &$name;
+            ::core::result::Result::Err(($make_err)())
+        } else {
+            ::core::result::Result::Ok(())
+        }
+    }};
+    ($name:expr, $make_err:expr, $($details:tt)+) => {{
+        if false {
+            let _ = &$name;
+            let _ = $crate::deps::serde_json::json!($($details)+);
+            ::core::result::Result::Err(($make_err)())
+        } else {
+            ::core::result::Result::Ok(())
+        }
+    }};
+}
+
+#[macro_export]
+macro_rules! latency_fault {
+    ($name:expr, max = $max:expr, distribution = $distribution:expr $(, $($details:tt)+)?) => {
+        if false {
+            let _ = &$name;
+            let _: ::std::time::Duration = $max;
+            let _: $crate::fault::LatencyDistribution = $distribution;
+            $(let _ = $crate::deps::serde_json::json!($($details)+);)?
+        }
+    };
+    ($name:expr, max = $max:expr $(, $($details:tt)+)?) => {
+        if false {
+            let _ = &$name;
+            let _: ::std::time::Duration = $max;
+            $(let _ = $crate::deps::serde_json::json!($($details)+);)?
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! latency_fault_async {
+    ($($args:tt)+) => {
+        $crate::latency_fault!($($args)+)
+    };
+}
+
+#[macro_export]
+macro_rules! crash_point {
+    ($name:expr) => {
+        if false {
+            let _ = &$name;
+        }
+    };
+    ($name:expr, $($details:tt)+) => {
+        if false {
+            let _ = &$name;
+            let _ = $crate::deps::serde_json::json!($($details)+);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! yield_point {
+    ($name:expr) => {
+        if false {
+            let _ = &$name;
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! yield_point_async {
+    ($name:expr) => {
+        $crate::yield_point!($name)
+    };
+}
+
+#[cfg(feature = "getrandom")]
+#[macro_export]
+macro_rules! register_getrandom {
+    () => {};
+}
@@ -0,0 +1,637 @@
+//! Fault-injecting wrappers for TCP streams and listeners, and network
+//! partitions between labeled peers.
+//!
+//! Every [`TcpListener`] and [`TcpStream`] is created with a label naming the
+//! peer it belongs to, such as `"node-1"`. Faults are registered per label,
+//! for example `"node-1.reset"`, and streams accepted by a listener share the
+//! listener's faults:
+//!
+//! - `{label}.reset`: the connection is reset and every later operation fails
+//!   with [`ErrorKind::ConnectionReset`].
+//! - `{label}.delay`: the operation is delayed by up to 50ms.
+//! - `{label}.drop_trailing_bytes`: a write only sends a prefix of its buffer
+//!   but reports success, and the connection is then closed for writing.
+//! - `{label}.half_open`: the connection silently stops carrying data in
+//!   either direction without being closed. Writes are discarded, incoming
+//!   data is dropped, and reads block until the read timeout elapses, even
+//!   once the peer closes the connection.
+//! - `{label}.accept_failure`: an incoming connection is dropped by
+//!   [`TcpListener::accept`], which fails with
+//!   [`ErrorKind::ConnectionAborted`].
+//!
+//! [`partition`] blocks traffic between two labels for a span of time: new
+//! connections between them fail and reads and writes block until the
+//! partition heals. Peers are identified by the local address of each
+//! listener and connected stream, so listeners should bind to a concrete
+//! address such as `127.0.0.1:0` rather than `0.0.0.0`.
+//!
+#![cfg_attr(
+    feature = "tokio",
+    doc = "With the `tokio` feature, [`tokio`] provides async equivalents."
+)]
+#![cfg_attr(
+    not(feature = "tokio"),
+    doc = "With the `tokio` feature, `tokio` provides async equivalents."
+)]
+//!
+//! When the `enabled` feature is off no faults are registered, but partitions
+//! still apply.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, ErrorKind, Read, Write},
+    net::{self as std_net, Shutdown, SocketAddr, ToSocketAddrs},
+    sync::{Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "enabled")]
+use crate::fault::{FaultEntry, LatencyDistribution, register};
+
+#[cfg(feature = "tokio")]
+pub mod tokio;
+
+/// The maximum delay injected by the `{label}.delay` fault.
+#[cfg(feature = "enabled")]
+const MAX_DELAY: Duration = Duration::from_millis(50);
+
+#[derive(Debug)]
+struct Partition {
+    a: String,
+    b: String,
+    until: Instant,
+}
+
+impl Partition {
+    fn separates(&self, a: &str, b: &str) -> bool {
+        (self.a == a && self.b == b) || (self.a == b && self.b == a)
+    }
+}
+
+static PARTITIONS: Mutex<Vec<Partition>> = Mutex::new(Vec::new());
+
+/// Blocks traffic between the peers labeled `a` and `b` for `duration`,
+/// replacing any existing partition between them.
+///
+/// The partition is reported as a `precept_net_partition` event.
+pub fn partition(a: &str, b: &str, duration: Duration) {
+    let mut partitions = PARTITIONS.lock().unwrap_or_else(PoisonError::into_inner);
+    partitions.retain(|p| !p.separates(a, b));
+    partitions.push(Partition {
+        a: a.to_owned(),
+        b: b.to_owned(),
+        until: Instant::now() + duration,
+    });
+    drop(partitions);
+    crate::dispatch::emit(crate::dispatch::Event::Custom {
+        name: "precept_net_partition",
+        value: serde_json::json!({
+            "a": a,
+            "b": b,
+            "duration_ms": u64::try_from(duration.as_millis()).unwrap_or(u64::MAX),
+        }),
+    });
+}
+
+/// Heals the partition between the peers labeled `a` and `b`, if any.
+pub fn heal(a: &str, b: &str) {
+    let mut partitions = PARTITIONS.lock().unwrap_or_else(PoisonError::into_inner);
+    partitions.retain(|p| !p.separates(a, b));
+}
+
+/// Heals every partition.
+pub fn heal_all() {
+    PARTITIONS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clear();
+}
+
+/// Returns when the partition between the peers labeled `a` and `b` heals,
+/// or `None` if they are not partitioned.
+pub fn partitioned_until(a: &str, b: &str) -> Option<Instant> {
+    let mut partitions = PARTITIONS.lock().unwrap_or_else(PoisonError::into_inner);
+    let now = Instant::now();
+    partitions.retain(|p| p.until > now);
+    partitions
+        .iter()
+        .find(|p| p.separates(a, b))
+        .map(|p| p.until)
+}
+
+/// The label of each listener and connected stream, by local address.
+static LABELS: Mutex<BTreeMap<SocketAddr, String>> = Mutex::new(BTreeMap::new());
+
+fn register_addr(addr: SocketAddr, label: &str) {
+    let mut labels = LABELS.lock().unwrap_or_else(PoisonError::into_inner);
+    labels.insert(addr, label.to_owned());
+}
+
+fn unregister_addr(addr: SocketAddr) {
+    let mut labels = LABELS.lock().unwrap_or_else(PoisonError::into_inner);
+    labels.remove(&addr);
+}
+
+/// Returns the label of the peer at `addr`, if it is known.
+fn label_of(addr: SocketAddr) -> Option<String> {
+    let labels = LABELS.lock().unwrap_or_else(PoisonError::into_inner);
+    labels.get(&addr).cloned()
+}
+
+/// Returns an error if any of `addrs` belongs to a peer partitioned from
+/// `label`.
+fn check_connect(label: &str, addrs: &[SocketAddr]) -> io::Result<()> {
+    for &addr in addrs {
+        if let Some(peer) = label_of(addr) {
+            if partitioned_until(label, &peer).is_some() {
+                return Err(io::Error::new(
+                    ErrorKind::TimedOut,
+                    format!("{label} is partitioned from {peer}"),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Registers the faults of the peer labeled `label`, so that they can be
+/// configured before any connection is made.
+#[cfg_attr(not(feature = "enabled"), allow(unused_variables))]
+fn register_faults(label: &str) {
+    #[cfg(feature = "enabled")]
+    {
+        register(&format!("{label}.accept_failure"));
+        LinkFaults::new(label);
+    }
+}
+
+#[cfg_attr(not(feature = "enabled"), allow(unused_variables))]
+#[track_caller]
+fn accept_failure(label: &str) -> bool {
+    #[cfg(feature = "enabled")]
+    return crate::io::trip(register(&format!("{label}.accept_failure")), label);
+    #[cfg(not(feature = "enabled"))]
+    false
+}
+
+fn accept_error() -> io::Error {
+    io::Error::new(
+        ErrorKind::ConnectionAborted,
+        "precept injected accept failure",
+    )
+}
+
+#[cfg(feature = "enabled")]
+#[derive(Debug, Clone, Copy)]
+struct LinkFaults {
+    reset: &'static FaultEntry,
+    delay: &'static FaultEntry,
+    drop_trailing_bytes: &'static FaultEntry,
+    half_open: &'static FaultEntry,
+}
+
+#[cfg(feature = "enabled")]
+impl LinkFaults {
+    fn new(label: &str) -> Self {
+        Self {
+            reset: register(&format!("{label}.reset")),
+            delay: register(&format!("{label}.delay")),
+            drop_trailing_bytes: register(&format!("{label}.drop_trailing_bytes")),
+            half_open: register(&format!("{label}.half_open")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(feature = "enabled"), allow(dead_code))]
+enum LinkState {
+    Open,
+    Reset,
+    HalfOpen,
+    /// Trailing bytes were dropped and the connection closed for writing.
+    Truncated,
+}
+
+/// How a write should be carried out.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(not(feature = "enabled"), allow(dead_code))]
+enum WriteAction {
+    Write,
+    Discard,
+    Truncate(usize),
+}
+
+/// The fault state of one end of a connection.
+#[derive(Debug)]
+struct Link {
+    label: String,
+    peer: SocketAddr,
+    state: LinkState,
+    #[cfg(feature = "enabled")]
+    faults: LinkFaults,
+}
+
+impl Link {
+    fn new(label: &str, peer: SocketAddr) -> Self {
+        Self {
+            label: label.to_owned(),
+            peer,
+            state: LinkState::Open,
+            #[cfg(feature = "enabled")]
+            faults: LinkFaults::new(label),
+        }
+    }
+
+    /// Checks partitions and connection faults before an operation.
+    ///
+    /// Returns the instant the operation must wait until, if any, or an error
+    /// if the connection has been reset.
+    #[track_caller]
+    fn check(&mut self) -> io::Result<Option<Instant>> {
+        if self.state == LinkState::Reset {
+            return Err(io::Error::new(
+                ErrorKind::ConnectionReset,
+                "precept injected connection reset",
+            ));
+        }
+        if let Some(until) =
+            label_of(self.peer).and_then(|peer| partitioned_until(&self.label, &peer))
+        {
+            return Ok(Some(until));
+        }
+        #[cfg(feature = "enabled")]
+        {
+            if crate::io::trip(self.faults.reset, &self.label) {
+                self.state = LinkState::Reset;
+                return self.check();
+            }
+            if self.state == LinkState::Open && crate::io::trip(self.faults.half_open, &self.label)
+            {
+                self.state = LinkState::HalfOpen;
+            }
+            let delay = self
+                .faults
+                .delay
+                .trip_latency(MAX_DELAY, LatencyDistribution::default());
+            if let Some(delay) = delay {
+                crate::emit_event!("precept_fault", {
+                    "name": self.faults.delay.name(),
+                    "details": {
+                        "label": self.label,
+                        "delay_us": u64::try_from(delay.as_micros()).unwrap_or(u64::MAX),
+                    },
+                });
+                return Ok(Some(Instant::now() + delay));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Decides how to carry out a write of `len` bytes.
+    #[cfg_attr(not(feature = "enabled"), allow(unused_variables))]
+    #[track_caller]
+    fn write_action(&mut self, len: usize) -> io::Result<WriteAction> {
+        match self.state {
+            LinkState::HalfOpen => return Ok(WriteAction::Discard),
+            LinkState::Truncated => {
+                return Err(io::Error::new(
+                    ErrorKind::BrokenPipe,
+                    "precept injected connection close",
+                ));
+            }
+            _ => {}
+        }
+        #[cfg(feature = "enabled")]
+        if crate::io::trip(self.faults.drop_trailing_bytes, &self.label) {
+            self.state = LinkState::Truncated;
+            return Ok(WriteAction::Truncate(crate::io::short_len(
+                self.faults.drop_trailing_bytes,
+                len,
+            )));
+        }
+        Ok(WriteAction::Write)
+    }
+
+    fn is_half_open(&self) -> bool {
+        self.state == LinkState::HalfOpen
+    }
+}
+
+/// Blocks the current thread until `until`.
+fn sleep_until(until: Instant) {
+    let now = Instant::now();
+    if until > now {
+        thread::sleep(until - now);
+    }
+}
+
+/// A TCP listener whose accepted streams inject faults.
+///
+/// See the [module docs](self) for the injected failures.
+#[derive(Debug)]
+pub struct TcpListener {
+    inner: std_net::TcpListener,
+    label: String,
+    local_addr: SocketAddr,
+}
+
+impl TcpListener {
+    /// Binds a listener for the peer labeled `label`.
+    pub fn bind(label: &str, addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let inner = std_net::TcpListener::bind(addr)?;
+        let local_addr = inner.local_addr()?;
+        register_faults(label);
+        register_addr(local_addr, label);
+        Ok(Self {
+            inner,
+            label: label.to_owned(),
+            local_addr,
+        })
+    }
+
+    /// Accepts a new incoming connection.
+    #[track_caller]
+    pub fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let (stream, addr) = self.inner.accept()?;
+        if accept_failure(&self.label) {
+            let _ = stream.shutdown(Shutdown::Both);
+            return Err(accept_error());
+        }
+        Ok((TcpStream::from_std(&self.label, stream, addr, None), addr))
+    }
+
+    /// Returns the local address of this listener.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    /// Returns the label of this listener.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Returns a reference to the wrapped listener.
+    pub fn get_ref(&self) -> &std_net::TcpListener {
+        &self.inner
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        unregister_addr(self.local_addr);
+    }
+}
+
+/// A TCP stream which injects connection faults.
+///
+/// See the [module docs](self) for the injected failures.
+#[derive(Debug)]
+pub struct TcpStream {
+    inner: std_net::TcpStream,
+    link: Link,
+    /// The local address registered for this stream, if it connected.
+    registered: Option<SocketAddr>,
+}
+
+impl TcpStream {
+    /// Opens a connection from the peer labeled `label` to `addr`.
+    ///
+    /// Fails with [`ErrorKind::TimedOut`] if `addr` belongs to a peer
+    /// partitioned from `label`.
+    pub fn connect(label: &str, addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let addrs: Vec<_> = addr.to_socket_addrs()?.collect();
+        check_connect(label, &addrs)?;
+        let inner = std_net::TcpStream::connect(&addrs[..])?;
+        let peer = inner.peer_addr()?;
+        let local = inner.local_addr()?;
+        register_addr(local, label);
+        Ok(Self::from_std(label, inner, peer, Some(local)))
+    }
+
+    fn from_std(
+        label: &str,
+        inner: std_net::TcpStream,
+        peer: SocketAddr,
+        registered: Option<SocketAddr>,
+    ) -> Self {
+        Self {
+            inner,
+            link: Link::new(label, peer),
+            registered,
+        }
+    }
+
+    /// Waits out partitions and injected delays before an operation.
+    #[track_caller]
+    fn wait(&mut self) -> io::Result<()> {
+        match self.link.check() {
+            Ok(Some(until)) => {
+                sleep_until(until);
+                Ok(())
+            }
+            Ok(None) => Ok(()),
+            Err(err) => {
+                let _ = self.inner.shutdown(Shutdown::Both);
+                Err(err)
+            }
+        }
+    }
+
+    /// Drops incoming data without ever returning it or reporting the end of
+    /// the stream, blocking until the read timeout elapses.
+    fn read_half_open(&mut self) -> io::Result<usize> {
+        let deadline = self
+            .inner
+            .read_timeout()?
+            .and_then(|timeout| Instant::now().checked_add(timeout));
+        let mut scratch = [0; 1024];
+        // a read timeout or non-blocking socket surfaces as an error here
+        while self.inner.read(&mut scratch)? > 0 {}
+        // the peer closed the connection, which a half-open link never sees
+        match deadline {
+            Some(deadline) => {
+                sleep_until(deadline);
+                Err(io::Error::new(
+                    ErrorKind::TimedOut,
+                    "precept injected half-open connection",
+                ))
+            }
+            None => loop {
+                thread::park();
+            },
+        }
+    }
+
+    /// Returns the label of this end of the connection.
+    pub fn label(&self) -> &str {
+        &self.link.label
+    }
+
+    /// Returns the address of the remote peer.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.link.peer)
+    }
+
+    /// Returns the local address of this stream.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    /// Shuts down the read, write, or both halves of this connection.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.inner.shutdown(how)
+    }
+
+    /// Sets the read timeout of the underlying stream.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    /// Sets the write timeout of the underlying stream.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+
+    /// Sets `TCP_NODELAY` on the underlying stream.
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.inner.set_nodelay(nodelay)
+    }
+
+    /// Returns a reference to the wrapped stream.
+    pub fn get_ref(&self) -> &std_net::TcpStream {
+        &self.inner
+    }
+}
+
+impl Read for TcpStream {
+    #[track_caller]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.wait()?;
+        if self.link.is_half_open() {
+            return self.read_half_open();
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl Write for TcpStream {
+    #[track_caller]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.wait()?;
+        match self.link.write_action(buf.len())? {
+            WriteAction::Write => self.inner.write(buf),
+            WriteAction::Discard => Ok(buf.len()),
+            WriteAction::Truncate(len) => {
+                self.inner.write_all(&buf[..len])?;
+                let _ = self.inner.shutdown(Shutdown::Write);
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        if let Some(addr) = self.registered {
+            unregister_addr(addr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        time::{Duration, Instant},
+    };
+
+    use super::{TcpListener, TcpStream, heal, partition};
+
+    #[test]
+    fn partition_blocks_traffic() {
+        let _lock = crate::fault::lock_catalog();
+        let listener = TcpListener::bind("net_test_server", "127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut client = TcpStream::connect("net_test_client", addr).unwrap();
+        let _quiet = crate::fault::only_enable(&[]);
+        let (mut server, _) = listener.accept().unwrap();
+
+        partition(
+            "net_test_client",
+            "net_test_server",
+            Duration::from_secs(60),
+        );
+        let err = TcpStream::connect("net_test_client", addr).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        heal("net_test_server", "net_test_client");
+
+        partition(
+            "net_test_client",
+            "net_test_server",
+            Duration::from_millis(50),
+        );
+        let start = Instant::now();
+        client.write_all(b"ping").unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[cfg(feature = "enabled")]
+    #[test]
+    fn stream_faults() {
+        use crate::fault::get_fault_by_name;
+
+        let _lock = crate::fault::lock_catalog();
+        let listener = TcpListener::bind("net_test_faults", "127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _client = TcpStream::connect("net_test_faults_client", addr).unwrap();
+        let _quiet = crate::fault::only_enable(&[]);
+
+        let _forced = get_fault_by_name("net_test_faults.accept_failure")
+            .unwrap()
+            .force_scoped(1);
+        let err = listener.accept().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionAborted);
+
+        let mut client = TcpStream::connect("net_test_faults_client", addr).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        let _forced = get_fault_by_name("net_test_faults_client.drop_trailing_bytes")
+            .unwrap()
+            .force_scoped(1);
+        assert_eq!(client.write(b"hello world").unwrap(), 11);
+        let mut received = vec![];
+        server.read_to_end(&mut received).unwrap();
+        assert!(!received.is_empty() && b"hello world".starts_with(&received));
+        assert!(received.len() < 11);
+
+        let mut client = TcpStream::connect("net_test_faults_client", addr).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_millis(20)))
+            .unwrap();
+        let _forced = get_fault_by_name("net_test_faults.half_open")
+            .unwrap()
+            .force_scoped(1);
+        client.write_all(b"lost").unwrap();
+        drop(client);
+        let err = server.read(&mut [0; 4]).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+        ));
+
+        let _forced = get_fault_by_name("net_test_faults.reset")
+            .unwrap()
+            .force_scoped(1);
+        let err = server.write(b"x").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionReset);
+        assert!(server.write(b"x").is_err());
+    }
+}
@@ -0,0 +1,313 @@
+//! Async equivalents of the [`net`](super) wrappers for tokio.
+//!
+//! These inject the same faults and honor the same partitions as their
+//! blocking counterparts, registering faults under the same names. Delays and
+//! partitions suspend the task rather than blocking the thread, and a read
+//! from a half-open connection stays pending, so bound it with
+//! `tokio::time::timeout`.
+
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::Instant,
+};
+
+use ::tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{self as tokio_net, ToSocketAddrs},
+};
+
+use super::{
+    Link, WriteAction, accept_error, accept_failure, check_connect, register_addr, register_faults,
+    unregister_addr,
+};
+
+/// An async TCP listener whose accepted streams inject faults.
+///
+/// See the [module docs](super) for the injected failures.
+#[derive(Debug)]
+pub struct TcpListener {
+    inner: tokio_net::TcpListener,
+    label: String,
+    local_addr: SocketAddr,
+}
+
+impl TcpListener {
+    /// Binds a listener for the peer labeled `label`.
+    pub async fn bind(label: &str, addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let inner = tokio_net::TcpListener::bind(addr).await?;
+        let local_addr = inner.local_addr()?;
+        register_faults(label);
+        register_addr(local_addr, label);
+        Ok(Self {
+            inner,
+            label: label.to_owned(),
+            local_addr,
+        })
+    }
+
+    /// Accepts a new incoming connection.
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let (stream, addr) = self.inner.accept().await?;
+        if accept_failure(&self.label) {
+            drop(stream);
+            return Err(accept_error());
+        }
+        Ok((TcpStream::from_tokio(&self.label, stream, addr, None), addr))
+    }
+
+    /// Returns the local address of this listener.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    /// Returns the label of this listener.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Returns a reference to the wrapped listener.
+    pub fn get_ref(&self) -> &tokio_net::TcpListener {
+        &self.inner
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        unregister_addr(self.local_addr);
+    }
+}
+
+/// An async TCP stream which injects connection faults.
+///
+/// See the [module docs](super) for the injected failures.
+#[derive(Debug)]
+pub struct TcpStream {
+    inner: tokio_net::TcpStream,
+    link: Link,
+    /// The local address registered for this stream, if it connected.
+    registered: Option<SocketAddr>,
+    /// The end of a partition or injected delay the stream is waiting out.
+    deadline: Option<Instant>,
+    /// Whether a read whose inner poll returned [`Poll::Pending`] has already
+    /// been checked for partitions and faults.
+    read_checked: bool,
+    /// How a write whose inner poll returned [`Poll::Pending`] is carried
+    /// out, reused until the write completes.
+    pending_write: Option<WriteAction>,
+}
+
+impl TcpStream {
+    /// Opens a connection from the peer labeled `label` to `addr`.
+    ///
+    /// Fails with [`io::ErrorKind::TimedOut`] if `addr` belongs to a peer
+    /// partitioned from `label`.
+    pub async fn connect(label: &str, addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let addrs: Vec<_> = tokio_net::lookup_host(addr).await?.collect();
+        check_connect(label, &addrs)?;
+        let inner = tokio_net::TcpStream::connect(&addrs[..]).await?;
+        let peer = inner.peer_addr()?;
+        let local = inner.local_addr()?;
+        register_addr(local, label);
+        Ok(Self::from_tokio(label, inner, peer, Some(local)))
+    }
+
+    fn from_tokio(
+        label: &str,
+        inner: tokio_net::TcpStream,
+        peer: SocketAddr,
+        registered: Option<SocketAddr>,
+    ) -> Self {
+        Self {
+            inner,
+            link: Link::new(label, peer),
+            registered,
+            deadline: None,
+            read_checked: false,
+            pending_write: None,
+        }
+    }
+
+    /// Waits out partitions and injected delays before an operation.
+    fn poll_wait(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(deadline) = self.deadline {
+            if Instant::now() < deadline {
+                crate::timer::wake_at(deadline, cx.waker().clone());
+                return Poll::Pending;
+            }
+            // let the delayed operation proceed without another check
+            self.deadline = None;
+            return Poll::Ready(Ok(()));
+        }
+        match self.link.check() {
+            Ok(Some(deadline)) => {
+                self.deadline = Some(deadline);
+                crate::timer::wake_at(deadline, cx.waker().clone());
+                Poll::Pending
+            }
+            Ok(None) => Poll::Ready(Ok(())),
+            Err(err) => {
+                let _ = Pin::new(&mut self.inner).poll_shutdown(cx);
+                Poll::Ready(Err(err))
+            }
+        }
+    }
+
+    /// Drops incoming data without ever returning it or reporting the end of
+    /// the stream, so the read stays pending.
+    fn poll_read_half_open(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut scratch = [0; 1024];
+        loop {
+            let mut discard = ReadBuf::new(&mut scratch);
+            ready!(Pin::new(&mut self.inner).poll_read(cx, &mut discard))?;
+            if discard.filled().is_empty() {
+                // the peer closed the connection, which a half-open link
+                // never sees, so nothing will wake this read
+                return Poll::Pending;
+            }
+        }
+    }
+
+    /// Returns the label of this end of the connection.
+    pub fn label(&self) -> &str {
+        &self.link.label
+    }
+
+    /// Returns the address of the remote peer.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.link.peer)
+    }
+
+    /// Returns the local address of this stream.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    /// Sets `TCP_NODELAY` on the underlying stream.
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.inner.set_nodelay(nodelay)
+    }
+
+    /// Returns a reference to the wrapped stream.
+    pub fn get_ref(&self) -> &tokio_net::TcpStream {
+        &self.inner
+    }
+}
+
+impl AsyncRead for TcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !std::mem::take(&mut this.read_checked) {
+            ready!(this.poll_wait(cx))?;
+        }
+        let result = if this.link.is_half_open() {
+            this.poll_read_half_open(cx)
+        } else {
+            Pin::new(&mut this.inner).poll_read(cx, buf)
+        };
+        this.read_checked = result.is_pending();
+        result
+    }
+}
+
+impl AsyncWrite for TcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let action = match this.pending_write.take() {
+            Some(action) => action,
+            None => {
+                ready!(this.poll_wait(cx))?;
+                this.link.write_action(buf.len())?
+            }
+        };
+        let result = match action {
+            WriteAction::Write => Pin::new(&mut this.inner).poll_write(cx, buf),
+            WriteAction::Discard => Poll::Ready(Ok(buf.len())),
+            WriteAction::Truncate(len) => {
+                match Pin::new(&mut this.inner).poll_write(cx, &buf[..len]) {
+                    Poll::Ready(Ok(_)) => {
+                        let _ = Pin::new(&mut this.inner).poll_shutdown(cx);
+                        Poll::Ready(Ok(buf.len()))
+                    }
+                    result => result,
+                }
+            }
+        };
+        if result.is_pending() {
+            this.pending_write = Some(action);
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        if let Some(addr) = self.registered {
+            unregister_addr(addr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use ::tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{TcpListener, TcpStream};
+    use crate::net::partition;
+
+    #[test]
+    fn async_partition_delays_traffic() {
+        let _lock = crate::fault::lock_catalog();
+        let runtime = ::tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let listener = TcpListener::bind("net_async_server", "127.0.0.1:0")
+                .await
+                .unwrap();
+            let addr = listener.local_addr().unwrap();
+            let mut client = TcpStream::connect("net_async_client", addr).await.unwrap();
+            let _quiet = crate::fault::only_enable(&[]);
+            let (mut server, _) = listener.accept().await.unwrap();
+
+            partition(
+                "net_async_client",
+                "net_async_server",
+                Duration::from_millis(50),
+            );
+            let err = TcpStream::connect("net_async_client", addr)
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+
+            let start = Instant::now();
+            client.write_all(b"ping").await.unwrap();
+            assert!(start.elapsed() >= Duration::from_millis(40));
+            let mut buf = [0; 4];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"ping");
+        });
+    }
+}
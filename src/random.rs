@@ -1,3 +1,9 @@
+use std::{
+    collections::BTreeMap,
+    ops::{Bound, RangeBounds},
+    sync::{Mutex, PoisonError},
+};
+
 use rand::Rng;
 
 /// Returns a random number generator that uses the dispatcher for randomness.
@@ -20,6 +26,97 @@ pub fn rng() -> impl Rng {
     DispatchRng
 }
 
+/// Returns a random number generator which draws from an independent
+/// substream labeled `label`.
+///
+/// Draws from one substream don't shift the values drawn from any other, so a
+/// recorded seed keeps reproducing the same decisions in one component when
+/// another component changes how much randomness it uses. See
+/// [`Dispatch::random_for`](crate::dispatch::Dispatch::random_for).
+///
+/// When disabled, it returns the standard random generator.
+#[cfg(not(feature = "enabled"))]
+#[allow(unused_variables)]
+pub fn rng_for(label: &str) -> impl Rng {
+    rand::rng()
+}
+
+/// Returns a random number generator which draws from an independent
+/// substream labeled `label`.
+///
+/// Draws from one substream don't shift the values drawn from any other, so a
+/// recorded seed keeps reproducing the same decisions in one component when
+/// another component changes how much randomness it uses. See
+/// [`Dispatch::random_for`](crate::dispatch::Dispatch::random_for).
+///
+/// When disabled, it returns the standard random generator.
+#[cfg(feature = "enabled")]
+pub fn rng_for(label: &str) -> impl Rng {
+    LabeledRng { label: label.to_owned() }
+}
+
+/// Advances a splitmix64 generator, returning its next value.
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Independent generators derived from a single seed, one per label.
+#[derive(Debug)]
+pub(crate) struct Substreams {
+    seed: u64,
+    states: BTreeMap<String, u64>,
+}
+
+impl Substreams {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self { seed, states: BTreeMap::new() }
+    }
+
+    pub(crate) fn next(&mut self, label: &str) -> u64 {
+        if !self.states.contains_key(label) {
+            // FNV-1a keeps the derivation stable across Rust versions
+            let hash = label.bytes().fold(0xCBF2_9CE4_8422_2325_u64, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01B3)
+            });
+            let mut state = self.seed ^ hash;
+            let initial = splitmix64(&mut state);
+            self.states.insert(label.to_owned(), initial);
+        }
+        let state = self.states.get_mut(label).expect("substream was inserted");
+        splitmix64(state)
+    }
+}
+
+static SUBSTREAMS: Mutex<Option<Substreams>> = Mutex::new(None);
+
+/// Derives the process-wide substreams from `seed`, replacing any drawn so
+/// far.
+pub(crate) fn seed_substreams(seed: u64) {
+    #[cfg(all(feature = "getrandom", feature = "enabled"))]
+    {
+        let fallback = Substreams::new(seed).next(GETRANDOM_LABEL);
+        *GETRANDOM_FALLBACK
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(fallback);
+    }
+    *SUBSTREAMS.lock().unwrap_or_else(PoisonError::into_inner) = Some(Substreams::new(seed));
+}
+
+/// Returns the next value of the process-wide substream labeled `label`.
+///
+/// The substreams are normally seeded by [`init`](crate::init). Without it,
+/// the seed is drawn with `seed` on first use.
+pub(crate) fn next_in_substream(label: &str, seed: impl FnOnce() -> u64) -> u64 {
+    let mut substreams = SUBSTREAMS.lock().unwrap_or_else(PoisonError::into_inner);
+    substreams
+        .get_or_insert_with(|| Substreams::new(seed()))
+        .next(label)
+}
+
 #[cfg(feature = "enabled")]
 struct DispatchRng;
 
@@ -40,9 +137,193 @@ impl rand::TryRng for DispatchRng {
     }
 }
 
+#[cfg(feature = "enabled")]
+struct LabeledRng {
+    label: String,
+}
+
+#[cfg(feature = "enabled")]
+impl rand::TryRng for LabeledRng {
+    type Error = core::convert::Infallible;
+
+    fn try_next_u32(&mut self) -> Result<u32, Self::Error> {
+        Ok(self.try_next_u64()? as u32)
+    }
+
+    fn try_next_u64(&mut self) -> Result<u64, Self::Error> {
+        Ok(crate::dispatch::get_random_for(&self.label))
+    }
+
+    fn try_fill_bytes(&mut self, dst: &mut [u8]) -> Result<(), Self::Error> {
+        rand_core::utils::fill_bytes_via_next_word(dst, || self.try_next_u64())
+    }
+}
+
+/// Fills `dest` with random bytes drawn from the dispatcher.
+pub fn fill_bytes(dest: &mut [u8]) {
+    for chunk in dest.chunks_mut(8) {
+        let bytes = crate::dispatch::get_random().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
+
+/// Returns true with probability `p`, using at most one draw from the
+/// dispatcher.
+///
+/// Probabilities at or below 0 are always false and at or above 1 are always
+/// true, without drawing.
+pub fn bool_with_probability(p: f64) -> bool {
+    if p <= 0.0 || p.is_nan() {
+        false
+    } else if p >= 1.0 {
+        true
+    } else {
+        unit_from(crate::dispatch::get_random) < p
+    }
+}
+
+/// Returns a uniformly distributed value in `range`, drawn from the
+/// dispatcher without modulo bias.
+///
+/// # Panics
+///
+/// Panics if `range` is empty.
+pub fn range(range: impl RangeBounds<u64>) -> u64 {
+    range_from(range, crate::dispatch::get_random)
+}
+
+/// Like [`range`], but draws from the substream labeled `label`.
+pub(crate) fn range_for(label: &str, range: impl RangeBounds<u64>) -> u64 {
+    range_from(range, || crate::dispatch::get_random_for(label))
+}
+
+/// Returns a uniformly distributed value in `range` drawn from `random`.
+fn range_from(range: impl RangeBounds<u64>, mut random: impl FnMut() -> u64) -> u64 {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => Some(start),
+        Bound::Excluded(&start) => start.checked_add(1),
+        Bound::Unbounded => Some(0),
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => Some(end),
+        Bound::Excluded(&end) => end.checked_sub(1),
+        Bound::Unbounded => Some(u64::MAX),
+    };
+    let (Some(start), Some(end)) = (start, end) else {
+        panic!("cannot draw from an empty range");
+    };
+    assert!(start <= end, "cannot draw from an empty range");
+    match (end - start).checked_add(1) {
+        Some(span) => start + below_from(span, random),
+        // the range covers every u64
+        None => random(),
+    }
+}
+
+/// Chooses one of `options` with probability proportional to its weight,
+/// using a single draw from the dispatcher.
+///
+/// Returns `None` if there are no options or every weight is zero.
+pub fn choose_weighted<T>(options: &[(u32, T)]) -> Option<&T> {
+    let total: u64 = options.iter().map(|&(weight, _)| u64::from(weight)).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut pick = below_from(total, crate::dispatch::get_random);
+    for (weight, value) in options {
+        match pick.checked_sub(u64::from(*weight)) {
+            Some(rest) => pick = rest,
+            None => return Some(value),
+        }
+    }
+    unreachable!("pick is below the total weight")
+}
+
+/// Shuffles `slice` in place, with every permutation equally likely.
+pub fn shuffle<T>(slice: &mut [T]) {
+    for i in (1..slice.len()).rev() {
+        let j = below_from(i as u64 + 1, crate::dispatch::get_random);
+        slice.swap(i, j as usize);
+    }
+}
+
+/// Returns a uniformly distributed value below `n` without modulo bias,
+/// drawing from `random`.
+///
+/// Uses Lemire's widening multiply, which needs a second draw only rarely.
+pub(crate) fn below_from(n: u64, mut random: impl FnMut() -> u64) -> u64 {
+    assert!(n > 0, "cannot draw below zero");
+    let mut product = u128::from(random()) * u128::from(n);
+    if (product as u64) < n {
+        let threshold = n.wrapping_neg() % n;
+        while (product as u64) < threshold {
+            product = u128::from(random()) * u128::from(n);
+        }
+    }
+    (product >> 64) as u64
+}
+
+/// Returns a uniformly distributed float in `[0, 1)` drawn from `random`.
+pub(crate) fn unit_from(mut random: impl FnMut() -> u64) -> f64 {
+    // 53 random bits fill the mantissa of an f64
+    (random() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Labels the substream which seeds [`GETRANDOM_FALLBACK`].
+#[cfg(all(feature = "getrandom", feature = "enabled"))]
+const GETRANDOM_LABEL: &str = "random.getrandom";
+
+/// The state of the splitmix64 generator which serves `getrandom` calls made
+/// by the dispatcher itself, seeded by [`init`](crate::init).
+#[cfg(all(feature = "getrandom", feature = "enabled"))]
+static GETRANDOM_FALLBACK: Mutex<Option<u64>> = Mutex::new(None);
+
+/// The `getrandom` backend defined by
+/// [`register_getrandom!`](crate::register_getrandom).
+///
+/// A dispatcher which itself calls `getrandom`, as the default dispatchers do
+/// through `rand`, would otherwise recurse forever. Such nested calls are
+/// served by a splitmix64 generator seeded by [`init`](crate::init) instead,
+/// or from the standard library's hasher keys if precept wasn't initialized.
+#[cfg(all(feature = "getrandom", feature = "enabled"))]
+#[doc(hidden)]
+pub fn getrandom_backend(dest: &mut [u8]) -> Result<(), getrandom::Error> {
+    use std::cell::Cell;
+
+    thread_local! {
+        static FILLING: Cell<bool> = const { Cell::new(false) };
+    }
+
+    struct Filling;
+
+    impl Drop for Filling {
+        fn drop(&mut self) {
+            FILLING.with(|filling| filling.set(false));
+        }
+    }
+
+    if FILLING.with(|filling| filling.replace(true)) {
+        let mut fallback = GETRANDOM_FALLBACK
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        // std draws its hasher keys from the OS without calling `getrandom`
+        let state = fallback.get_or_insert_with(|| {
+            use std::hash::BuildHasher;
+            std::hash::RandomState::new().hash_one(GETRANDOM_LABEL)
+        });
+        for chunk in dest.chunks_mut(8) {
+            chunk.copy_from_slice(&splitmix64(state).to_le_bytes()[..chunk.len()]);
+        }
+        return Ok(());
+    }
+    let _filling = Filling;
+    fill_bytes(dest);
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use super::rng;
+    use super::{Substreams, below_from, rng, rng_for};
     use rand::RngExt;
 
     #[test]
@@ -54,6 +335,102 @@ mod tests {
         let _: bool = rng.random();
     }
 
+    #[test]
+    fn substreams_are_independent() {
+        let mut quiet = Substreams::new(42);
+        let a: Vec<_> = (0..3).map(|_| quiet.next("a")).collect();
+
+        let mut noisy = Substreams::new(42);
+        let mut interleaved = vec![];
+        for _ in 0..3 {
+            noisy.next("b");
+            interleaved.push(noisy.next("a"));
+            noisy.next("b");
+        }
+        assert_eq!(a, interleaved);
+        assert_ne!(a[0], Substreams::new(42).next("b"));
+        assert_ne!(a[0], Substreams::new(43).next("a"));
+
+        let _: u64 = rng_for("a").random();
+    }
+
+    #[test]
+    fn helpers_stay_in_bounds() {
+        assert_eq!(below_from(1, || u64::MAX), 0);
+        assert_eq!(below_from(3, || u64::MAX), 2);
+        // the first draw lands in the biased zone and is rejected
+        let mut draws = [0, u64::MAX].into_iter();
+        assert_eq!(below_from(3, || draws.next().unwrap()), 2);
+
+        for _ in 0..100 {
+            assert!((5..8).contains(&super::range(5..8)));
+            assert_eq!(super::range(9..=9), 9);
+        }
+        let _ = super::range(..);
+        assert!(!super::bool_with_probability(0.0));
+        assert!(super::bool_with_probability(1.0));
+
+        assert_eq!(
+            super::choose_weighted(&[(0, 'a'), (1, 'b'), (0, 'c')]),
+            Some(&'b')
+        );
+        assert_eq!(super::choose_weighted::<char>(&[(0, 'a')]), None);
+
+        let mut values = [1, 2, 3, 4, 5];
+        super::shuffle(&mut values);
+        values.sort_unstable();
+        assert_eq!(values, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn fill_bytes_fills_partial_words() {
+        let mut bytes = [0_u8; 13];
+        super::fill_bytes(&mut bytes);
+        assert_ne!(bytes[8..], [0; 5]);
+
+        #[cfg(feature = "enabled")]
+        {
+            use crate::dispatch::{
+                Dispatch, dispatch_scoped, noop::NoopDispatch, seeded::SeededDispatch,
+            };
+
+            static NOOP: NoopDispatch = NoopDispatch;
+            let _scoped = dispatch_scoped(Box::leak(Box::new(SeededDispatch::new(7, &NOOP))));
+            super::fill_bytes(&mut bytes);
+            let expected = SeededDispatch::new(7, &NOOP);
+            let (first, second) = (expected.random(), expected.random());
+            assert_eq!(bytes[..8], first.to_le_bytes());
+            assert_eq!(bytes[8..], second.to_le_bytes()[..5]);
+        }
+    }
+
+    #[cfg(all(feature = "getrandom", feature = "enabled"))]
+    #[test]
+    fn getrandom_backend_serves_the_default_dispatcher() {
+        use crate::dispatch::{Dispatch, Event, dispatch_scoped};
+
+        /// Draws like the default dispatchers once the backend is registered:
+        /// `rand` calls `getrandom`, which calls back into the backend, and
+        /// panics if it fails.
+        struct OsBacked;
+
+        impl Dispatch for OsBacked {
+            fn emit(&self, _: Event) {}
+
+            fn random(&self) -> u64 {
+                let mut bytes = [0; 8];
+                super::getrandom_backend(&mut bytes).expect("getrandom failed");
+                u64::from_le_bytes(bytes)
+            }
+        }
+
+        static OS_BACKED: OsBacked = OsBacked;
+        let _scoped = dispatch_scoped(&OS_BACKED);
+        let mut bytes = [0; 16];
+        super::getrandom_backend(&mut bytes).unwrap();
+        assert_ne!(bytes, [0; 16]);
+    }
+
     #[test]
     fn rng_can_fill_byte_buffer() {
         let mut rng = rng();
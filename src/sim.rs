@@ -0,0 +1,712 @@
+//! A deterministic in-process simulator which hosts several logical nodes.
+//!
+//! A [`Sim`] runs each node as a future on the current thread. A single
+//! scheduler picks which runnable node to poll next, drawing its choices from
+//! the dispatcher, so a run is fully reproducible when the dispatcher is
+//! seeded, for example with a [`SeededDispatch`](crate::dispatch::seeded::SeededDispatch).
+//! Expectations and fault points work unchanged inside nodes.
+//!
+//! Nodes communicate over a virtual network via their [`Node`] handle.
+//! Messages are delivered after a random latency, are lost when the
+//! [`SIM_MESSAGE_LOSS`] fault trips, and are dropped while the sender and
+//! receiver are partitioned.
+//!
+//! Time is virtual: it only moves when every node is blocked, jumping
+//! straight to the next timer or message delivery. Each jump also advances
+//! the [`time`](crate::time) virtual clock.
+//!
+//! ```
+//! use std::time::Duration;
+//! use precept::sim::Sim;
+//!
+//! let mut sim = Sim::new();
+//! sim.host("server", |node| async move {
+//!     loop {
+//!         let msg = node.recv().await;
+//!         node.send(&msg.from, msg.payload);
+//!     }
+//! });
+//! sim.client("client", |node| async move {
+//!     node.send("server", "ping");
+//!     assert_eq!(node.recv().await.payload, b"ping");
+//!     node.sleep(Duration::from_secs(1)).await;
+//! });
+//! sim.run().unwrap();
+//! ```
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll, Wake, Waker},
+    time::Duration,
+};
+
+crate::declare_fault! {
+    /// Drops a message sent over the simulated network.
+    pub SIM_MESSAGE_LOSS = "sim.message_loss";
+}
+
+/// The default range of message latencies.
+const DEFAULT_LATENCY: (Duration, Duration) = (Duration::from_millis(1), Duration::from_millis(10));
+
+/// The default limit on virtual time.
+const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(3600);
+
+/// The default limit on the number of times nodes are polled.
+const DEFAULT_POLL_LIMIT: u64 = 10_000_000;
+
+/// A message delivered over the simulated network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// The name of the sending node.
+    pub from: String,
+    /// The message contents.
+    pub payload: Vec<u8>,
+}
+
+/// Error returned by [`Sim::run`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimError {
+    /// Every node is blocked and no timers or messages are pending, but the
+    /// named clients haven't finished.
+    Deadlock {
+        /// The clients which haven't finished.
+        blocked: Vec<String>,
+    },
+    /// The clients didn't finish within the time limit.
+    TimeLimit {
+        /// The time limit.
+        limit: Duration,
+    },
+    /// The clients didn't finish within the poll limit, for example because
+    /// a node keeps waking itself without letting virtual time advance.
+    PollLimit {
+        /// The poll limit.
+        limit: u64,
+    },
+}
+
+impl Error for SimError {}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Deadlock { blocked } => {
+                write!(f, "simulation deadlocked with blocked clients {blocked:?}")
+            }
+            Self::TimeLimit { limit } => {
+                write!(f, "simulation exceeded its time limit of {limit:?}")
+            }
+            Self::PollLimit { limit } => {
+                write!(f, "simulation exceeded its limit of {limit} polls")
+            }
+        }
+    }
+}
+
+/// A deterministic simulation of several nodes.
+///
+/// See the [module docs](self) for details.
+pub struct Sim {
+    world: Rc<RefCell<World>>,
+    tasks: Vec<Task>,
+    time_limit: Duration,
+    poll_limit: u64,
+}
+
+struct Task {
+    name: String,
+    client: bool,
+    future: Option<Pin<Box<dyn Future<Output = ()>>>>,
+}
+
+impl Sim {
+    /// Creates an empty simulation.
+    pub fn new() -> Self {
+        Self {
+            world: Rc::new(RefCell::new(World {
+                elapsed: Duration::ZERO,
+                latency: DEFAULT_LATENCY,
+                partitions: BTreeSet::new(),
+                next_seq: 0,
+                timers: BTreeMap::new(),
+                in_flight: BTreeMap::new(),
+                inboxes: BTreeMap::new(),
+            })),
+            tasks: vec![],
+            time_limit: DEFAULT_TIME_LIMIT,
+            poll_limit: DEFAULT_POLL_LIMIT,
+        }
+    }
+
+    /// Sets the range of message latencies. Defaults to 1ms to 10ms.
+    pub fn latency(self, min: Duration, max: Duration) -> Self {
+        assert!(min <= max, "minimum latency exceeds maximum latency");
+        self.world.borrow_mut().latency = (min, max);
+        self
+    }
+
+    /// Sets the limit on virtual time, after which [`run`](Self::run) fails.
+    /// Defaults to one hour.
+    pub fn time_limit(mut self, limit: Duration) -> Self {
+        self.time_limit = limit;
+        self
+    }
+
+    /// Sets the limit on the number of times nodes are polled, after which
+    /// [`run`](Self::run) fails. Defaults to ten million.
+    ///
+    /// Virtual time doesn't move while a node keeps waking itself, so this
+    /// bounds simulations which the time limit can't.
+    pub fn poll_limit(mut self, limit: u64) -> Self {
+        self.poll_limit = limit;
+        self
+    }
+
+    /// Adds a node which runs until the simulation ends, such as a server.
+    pub fn host<F, Fut>(&mut self, name: &str, node: F)
+    where
+        F: FnOnce(Node) -> Fut,
+        Fut: Future<Output = ()> + 'static,
+    {
+        self.add(name, false, node);
+    }
+
+    /// Adds a node which the simulation runs to completion. The simulation
+    /// ends once every client has finished.
+    pub fn client<F, Fut>(&mut self, name: &str, node: F)
+    where
+        F: FnOnce(Node) -> Fut,
+        Fut: Future<Output = ()> + 'static,
+    {
+        self.add(name, true, node);
+    }
+
+    fn add<F, Fut>(&mut self, name: &str, client: bool, node: F)
+    where
+        F: FnOnce(Node) -> Fut,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let mut world = self.world.borrow_mut();
+        assert!(
+            world
+                .inboxes
+                .insert(name.to_owned(), Inbox::default())
+                .is_none(),
+            "duplicate simulated node {name}"
+        );
+        drop(world);
+        let handle = Node {
+            name: name.into(),
+            world: self.world.clone(),
+        };
+        self.tasks.push(Task {
+            name: name.to_owned(),
+            client,
+            future: Some(Box::pin(node(handle))),
+        });
+    }
+
+    /// Partitions the nodes `a` and `b`, dropping messages between them until
+    /// they are healed.
+    pub fn partition(&self, a: &str, b: &str) {
+        self.world.borrow_mut().partition(a, b);
+    }
+
+    /// Heals a partition between the nodes `a` and `b`.
+    pub fn heal(&self, a: &str, b: &str) {
+        self.world.borrow_mut().heal(a, b);
+    }
+
+    /// Runs the simulation until every client has finished.
+    pub fn run(mut self) -> Result<(), SimError> {
+        let ready = Arc::new(Mutex::new((0..self.tasks.len()).collect::<BTreeSet<_>>()));
+        let wakers: Vec<Waker> = (0..self.tasks.len())
+            .map(|id| Waker::from(Arc::new(TaskWaker { id, ready: ready.clone() })))
+            .collect();
+        let mut polls = 0;
+
+        loop {
+            let blocked: Vec<String> = self
+                .tasks
+                .iter()
+                .filter(|task| task.client && task.future.is_some())
+                .map(|task| task.name.clone())
+                .collect();
+            if blocked.is_empty() {
+                return Ok(());
+            }
+
+            let woken = self.world.borrow_mut().fire_due();
+            woken.into_iter().for_each(Waker::wake);
+
+            let runnable: Vec<usize> = ready
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter()
+                .copied()
+                .filter(|&id| self.tasks[id].future.is_some())
+                .collect();
+            if runnable.is_empty() {
+                let mut world = self.world.borrow_mut();
+                match world.next_event() {
+                    None => return Err(SimError::Deadlock { blocked }),
+                    Some(at) if at > self.time_limit => {
+                        return Err(SimError::TimeLimit { limit: self.time_limit });
+                    }
+                    Some(at) => {
+                        let jump = at - world.elapsed;
+                        world.elapsed = at;
+                        drop(world);
+                        crate::time::advance(jump);
+                    }
+                }
+                continue;
+            }
+
+            if polls == self.poll_limit {
+                return Err(SimError::PollLimit { limit: self.poll_limit });
+            }
+            polls += 1;
+
+            let id = runnable[crate::random::range(0..runnable.len() as u64) as usize];
+            ready
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove(&id);
+            let task = &mut self.tasks[id];
+            let mut cx = Context::from_waker(&wakers[id]);
+            if let Some(future) = &mut task.future {
+                if future.as_mut().poll(&mut cx).is_ready() {
+                    task.future = None;
+                }
+            }
+        }
+    }
+}
+
+impl Default for Sim {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Sim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nodes: Vec<_> = self.tasks.iter().map(|task| &task.name).collect();
+        f.debug_struct("Sim")
+            .field("nodes", &nodes)
+            .finish_non_exhaustive()
+    }
+}
+
+struct TaskWaker {
+    id: usize,
+    ready: Arc<Mutex<BTreeSet<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(self.id);
+    }
+}
+
+struct World {
+    /// The virtual time since the simulation started.
+    elapsed: Duration,
+    latency: (Duration, Duration),
+    /// Partitioned pairs of nodes, ordered within each pair.
+    partitions: BTreeSet<(String, String)>,
+    /// Orders timers and messages due at the same time.
+    next_seq: u64,
+    timers: BTreeMap<(Duration, u64), Waker>,
+    /// Messages keyed by delivery time, with the name of the receiving node.
+    in_flight: BTreeMap<(Duration, u64), (String, Message)>,
+    inboxes: BTreeMap<String, Inbox>,
+}
+
+#[derive(Default)]
+struct Inbox {
+    messages: VecDeque<Message>,
+    /// The wakers of pending [`Recv`]s, which may belong to different tasks
+    /// when a node polls several at once.
+    wakers: Vec<Waker>,
+}
+
+fn pair(a: &str, b: &str) -> (String, String) {
+    if a <= b {
+        (a.to_owned(), b.to_owned())
+    } else {
+        (b.to_owned(), a.to_owned())
+    }
+}
+
+impl World {
+    fn seq(&mut self) -> u64 {
+        self.next_seq += 1;
+        self.next_seq
+    }
+
+    fn partition(&mut self, a: &str, b: &str) {
+        self.partitions.insert(pair(a, b));
+        crate::dispatch::emit(crate::dispatch::Event::Custom {
+            name: "precept_sim_partition",
+            value: serde_json::json!({
+                "a": a,
+                "b": b,
+                "elapsed_us": u64::try_from(self.elapsed.as_micros()).unwrap_or(u64::MAX),
+            }),
+        });
+    }
+
+    fn heal(&mut self, a: &str, b: &str) {
+        self.partitions.remove(&pair(a, b));
+    }
+
+    fn send(&mut self, from: &str, to: &str, payload: Vec<u8>) {
+        let seq = self.seq();
+        if crate::ENABLED && SIM_MESSAGE_LOSS.trip() {
+            crate::emit_event!("precept_fault", {
+                "name": SIM_MESSAGE_LOSS.name(),
+                "details": { "from": from, "to": to, "seq": seq },
+            });
+            return;
+        }
+        let (min, max) = self.latency;
+        let span = u64::try_from((max - min).as_nanos()).unwrap_or(u64::MAX);
+        let latency = min + Duration::from_nanos(crate::random::range(0..=span));
+        let message = Message { from: from.to_owned(), payload };
+        self.in_flight
+            .insert((self.elapsed + latency, seq), (to.to_owned(), message));
+    }
+
+    /// Returns the time of the next timer or message delivery.
+    fn next_event(&self) -> Option<Duration> {
+        let timer = self.timers.keys().next().map(|&(at, _)| at);
+        let message = self.in_flight.keys().next().map(|&(at, _)| at);
+        timer.into_iter().chain(message).min()
+    }
+
+    /// Fires every timer and delivers every message which is due, returning
+    /// the wakers of the nodes to wake.
+    fn fire_due(&mut self) -> Vec<Waker> {
+        let mut woken = vec![];
+        while let Some(entry) = self.timers.first_entry() {
+            if entry.key().0 > self.elapsed {
+                break;
+            }
+            woken.push(entry.remove());
+        }
+        while let Some(entry) = self.in_flight.first_entry() {
+            if entry.key().0 > self.elapsed {
+                break;
+            }
+            let (to, message) = entry.remove();
+            if self.partitions.contains(&pair(&message.from, &to)) {
+                continue;
+            }
+            if let Some(inbox) = self.inboxes.get_mut(&to) {
+                inbox.messages.push_back(message);
+                woken.append(&mut inbox.wakers);
+            }
+        }
+        woken
+    }
+}
+
+/// A handle to a simulated node, passed to each node's future.
+#[derive(Clone)]
+pub struct Node {
+    name: Rc<str>,
+    world: Rc<RefCell<World>>,
+}
+
+impl Node {
+    /// Returns the name of this node.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the virtual time since the simulation started.
+    pub fn elapsed(&self) -> Duration {
+        self.world.borrow().elapsed
+    }
+
+    /// Sends `payload` to the node named `to`.
+    ///
+    /// Delivery is best effort: the message is dropped if it is lost, if the
+    /// nodes are partitioned when it arrives, or if there is no such node.
+    pub fn send(&self, to: &str, payload: impl Into<Vec<u8>>) {
+        self.world.borrow_mut().send(&self.name, to, payload.into());
+    }
+
+    /// Waits for the next message sent to this node.
+    pub fn recv(&self) -> Recv<'_> {
+        Recv { node: self }
+    }
+
+    /// Returns the next message sent to this node, if one has arrived.
+    pub fn try_recv(&self) -> Option<Message> {
+        let mut world = self.world.borrow_mut();
+        world.inboxes.get_mut(&*self.name)?.messages.pop_front()
+    }
+
+    /// Waits until `duration` has elapsed in virtual time.
+    pub fn sleep(&self, duration: Duration) -> Sleep<'_> {
+        Sleep {
+            node: self,
+            deadline: self.elapsed() + duration,
+            timer: None,
+        }
+    }
+
+    /// Partitions the nodes `a` and `b`, dropping messages between them until
+    /// they are healed.
+    pub fn partition(&self, a: &str, b: &str) {
+        self.world.borrow_mut().partition(a, b);
+    }
+
+    /// Heals a partition between the nodes `a` and `b`.
+    pub fn heal(&self, a: &str, b: &str) {
+        self.world.borrow_mut().heal(a, b);
+    }
+}
+
+impl fmt::Debug for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Node")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Future returned by [`Node::recv`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Recv<'a> {
+    node: &'a Node,
+}
+
+impl Future for Recv<'_> {
+    type Output = Message;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Message> {
+        let mut world = self.node.world.borrow_mut();
+        let inbox = world
+            .inboxes
+            .get_mut(&*self.node.name)
+            .expect("every node has an inbox");
+        match inbox.messages.pop_front() {
+            Some(message) => Poll::Ready(message),
+            None => {
+                if !inbox.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                    inbox.wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Future returned by [`Node::sleep`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Sleep<'a> {
+    node: &'a Node,
+    deadline: Duration,
+    /// The sequence number of the registered timer, once polled.
+    timer: Option<u64>,
+}
+
+impl Future for Sleep<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut world = self.node.world.borrow_mut();
+        if world.elapsed >= self.deadline {
+            return Poll::Ready(());
+        }
+        if self.timer.is_none() {
+            let seq = world.seq();
+            world
+                .timers
+                .insert((self.deadline, seq), cx.waker().clone());
+            drop(world);
+            self.timer = Some(seq);
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep<'_> {
+    fn drop(&mut self) {
+        // a sleep dropped before its deadline mustn't advance time to it
+        if let Some(seq) = self.timer {
+            self.node
+                .world
+                .borrow_mut()
+                .timers
+                .remove(&(self.deadline, seq));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future, pin::pin, task::Poll, time::Duration};
+
+    use super::{Sim, SimError};
+
+    #[test]
+    fn nodes_exchange_messages() {
+        let _lock = crate::fault::lock_catalog();
+        let _quiet = crate::fault::only_enable(&[]);
+
+        let mut sim = Sim::new();
+        sim.host("server", |node| async move {
+            loop {
+                let msg = node.recv().await;
+                node.send(&msg.from, msg.payload);
+            }
+        });
+        for client in ["a", "b"] {
+            sim.client(client, |node| async move {
+                for i in 0..3u8 {
+                    node.send("server", [i]);
+                    assert_eq!(node.recv().await.payload, [i]);
+                }
+                assert!(node.elapsed() >= Duration::from_millis(6));
+            });
+        }
+        sim.run().unwrap();
+    }
+
+    #[test]
+    fn partitions_drop_messages() {
+        let _lock = crate::fault::lock_catalog();
+        let _quiet = crate::fault::only_enable(&[]);
+
+        let mut sim = Sim::new();
+        sim.host("server", |node| async move {
+            loop {
+                let msg = node.recv().await;
+                node.send(&msg.from, msg.payload);
+            }
+        });
+        sim.client("client", |node| async move {
+            node.partition("client", "server");
+            node.send("server", "lost");
+            node.sleep(Duration::from_secs(1)).await;
+            assert_eq!(node.try_recv(), None);
+
+            node.heal("client", "server");
+            node.send("server", "echo");
+            assert_eq!(node.recv().await.payload, b"echo");
+        });
+        sim.run().unwrap();
+
+        let mut sim = Sim::new();
+        sim.client("lonely", |node| async move {
+            node.recv().await;
+        });
+        assert_eq!(
+            sim.run(),
+            Err(SimError::Deadlock { blocked: vec!["lonely".into()] })
+        );
+    }
+
+    #[test]
+    fn poll_limit_bounds_busy_nodes() {
+        let mut sim = Sim::new().poll_limit(100);
+        sim.client("busy", |_| {
+            future::poll_fn(|cx| {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            })
+        });
+        assert_eq!(sim.run(), Err(SimError::PollLimit { limit: 100 }));
+    }
+
+    #[test]
+    fn dropped_sleeps_cancel_their_timers() {
+        let mut sim = Sim::new();
+        sim.client("impatient", |node| async move {
+            {
+                let mut sleep = pin!(node.sleep(Duration::from_secs(7200)));
+                future::poll_fn(|cx| {
+                    assert_eq!(sleep.as_mut().poll(cx), Poll::Pending);
+                    Poll::Ready(())
+                })
+                .await;
+            }
+            node.recv().await;
+        });
+        // a leftover timer would jump past the time limit instead
+        assert_eq!(
+            sim.run(),
+            Err(SimError::Deadlock { blocked: vec!["impatient".into()] })
+        );
+    }
+
+    #[cfg(feature = "enabled")]
+    #[test]
+    fn same_seed_replays_run() {
+        use std::{cell::RefCell, rc::Rc};
+
+        use super::SIM_MESSAGE_LOSS;
+        use crate::dispatch::{dispatch_scoped, noop::NoopDispatch, seeded::SeededDispatch};
+
+        static NOOP: NoopDispatch = NoopDispatch;
+        let _lock = crate::fault::lock_catalog();
+        let _quiet = crate::fault::only_enable(&[SIM_MESSAGE_LOSS]);
+        let _setup = crate::dispatch::setup_scoped(true);
+
+        let run = || {
+            let dispatch = Box::leak(Box::new(SeededDispatch::new(7, &NOOP)));
+            let _scoped = dispatch_scoped(dispatch);
+            let trace = Rc::new(RefCell::new(vec![]));
+
+            let mut sim = Sim::new().latency(Duration::from_millis(1), Duration::from_millis(50));
+            let received = trace.clone();
+            sim.host("server", |node| async move {
+                loop {
+                    let msg = node.recv().await;
+                    received
+                        .borrow_mut()
+                        .push((msg.from, msg.payload, node.elapsed()));
+                }
+            });
+            for client in ["a", "b"] {
+                sim.client(client, |node| async move {
+                    for i in 0..20u8 {
+                        node.send("server", [i]);
+                        node.sleep(Duration::from_millis(1)).await;
+                    }
+                    node.sleep(Duration::from_secs(1)).await;
+                });
+            }
+            sim.run().unwrap();
+            trace.take()
+        };
+
+        let trace = run();
+        assert!(
+            !trace.is_empty() && trace.len() < 40,
+            "some messages are lost"
+        );
+        assert_eq!(trace, run());
+    }
+}
@@ -0,0 +1,323 @@
+//! Instrumented synchronization primitives which perturb lock acquisition and
+//! detect potential deadlocks.
+//!
+//! [`Mutex`] and [`RwLock`] mirror their [`std::sync`] counterparts. When the
+//! `enabled` feature is on they:
+//!
+//! - perturb the schedule before each acquisition via the [`SYNC_ACQUIRE`]
+//!   fault, and before each release via [`SYNC_HOLD`], in the same way as a
+//!   [`yield_point!`](crate::yield_point),
+//! - make `try_lock`, `try_read` and `try_write` fail spuriously with
+//!   [`TryLockError::WouldBlock`] when [`SYNC_TRY_LOCK_FAILURE`] trips, and
+//! - record the order in which each thread acquires locks into a global
+//!   lock-order graph. Acquiring locks in an order which closes a cycle in the
+//!   graph could deadlock, so it fails an `Unreachable` expectation whose
+//!   details name the cycle, even if this run didn't deadlock.
+//!
+//! ```
+//! use precept::sync::Mutex;
+//!
+//! let accounts = Mutex::new(vec![100, 50]);
+//! accounts.lock().unwrap()[0] -= 10;
+//! ```
+//!
+//! When the `enabled` feature is off these are aliases of the [`std::sync`]
+//! types.
+
+pub use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+
+#[cfg(feature = "enabled")]
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, btree_map::Entry},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[cfg(feature = "enabled")]
+use crate::interleave::Perturbation;
+
+#[cfg(not(feature = "enabled"))]
+pub use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+pub mod channel;
+
+#[cfg(feature = "enabled")]
+mod mutex;
+#[cfg(feature = "enabled")]
+mod rwlock;
+
+#[cfg(feature = "enabled")]
+pub use mutex::{Mutex, MutexGuard};
+#[cfg(feature = "enabled")]
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+crate::declare_fault! {
+    /// Perturbs the schedule before a lock is acquired.
+    pub SYNC_ACQUIRE = "sync.acquire";
+
+    /// Perturbs the schedule before a lock is released, stretching the time
+    /// it is held.
+    pub SYNC_HOLD = "sync.hold";
+
+    /// Makes a non-blocking lock acquisition fail spuriously.
+    pub SYNC_TRY_LOCK_FAILURE = "sync.try_lock_failure";
+}
+
+/// A lock's identity in the lock-order graph, assigned on first use.
+#[cfg(feature = "enabled")]
+#[derive(Debug, Default)]
+struct LockId(AtomicU64);
+
+#[cfg(feature = "enabled")]
+impl LockId {
+    const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    fn get(&self) -> u64 {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        match self.0.load(Ordering::Acquire) {
+            0 => {
+                let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+                match self
+                    .0
+                    .compare_exchange(0, id, Ordering::AcqRel, Ordering::Acquire)
+                {
+                    Ok(_) => id,
+                    Err(existing) => existing,
+                }
+            }
+            id => id,
+        }
+    }
+}
+
+/// Prunes the lock's edges from the lock-order graph, so the graph doesn't
+/// grow without bound as locks come and go.
+#[cfg(feature = "enabled")]
+impl Drop for LockId {
+    fn drop(&mut self) {
+        let id = *self.0.get_mut();
+        if id == 0 {
+            return;
+        }
+        let mut graph = LOCK_ORDER
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        graph.remove(&id);
+        graph.retain(|_, edges| {
+            edges.remove(&id);
+            !edges.is_empty()
+        });
+    }
+}
+
+#[cfg(feature = "enabled")]
+thread_local! {
+    /// The locks held by this thread, in acquisition order.
+    static HELD: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Edges from each lock to the locks acquired while holding it.
+#[cfg(feature = "enabled")]
+static LOCK_ORDER: std::sync::Mutex<BTreeMap<u64, BTreeSet<u64>>> =
+    std::sync::Mutex::new(BTreeMap::new());
+
+/// Prepares to block on the lock `id`, perturbing the schedule and recording
+/// the acquisition order.
+#[cfg(feature = "enabled")]
+fn before_acquire(id: u64) {
+    Perturbation::choose(SYNC_ACQUIRE).apply();
+    for cycle in record_order(id) {
+        crate::expect_unreachable!("precept sync: lock order cycle", { "cycle": cycle });
+    }
+}
+
+/// Adds edges to the lock `id` from every lock held by this thread, returning
+/// each cycle closed by a new edge.
+#[cfg(feature = "enabled")]
+fn record_order(id: u64) -> Vec<Vec<u64>> {
+    // the held locks are gone while thread-local destructors run
+    let held = HELD
+        .try_with(|held| held.borrow().clone())
+        .unwrap_or_default();
+    if held.is_empty() {
+        return vec![];
+    }
+    let mut graph = LOCK_ORDER
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    let mut cycles = vec![];
+    for from in held {
+        if from != id && graph.entry(from).or_default().insert(id) {
+            if let Some(mut cycle) = find_path(&graph, id, from) {
+                cycle.push(id);
+                cycles.push(cycle);
+            }
+        }
+    }
+    cycles
+}
+
+/// Returns a path of locks from `from` to `to` in the lock-order graph.
+#[cfg(feature = "enabled")]
+fn find_path(graph: &BTreeMap<u64, BTreeSet<u64>>, from: u64, to: u64) -> Option<Vec<u64>> {
+    let mut parents = BTreeMap::from([(from, from)]);
+    let mut stack = vec![from];
+    while let Some(lock) = stack.pop() {
+        if lock == to {
+            let mut path = vec![to];
+            let mut lock = to;
+            while lock != from {
+                lock = parents[&lock];
+                path.push(lock);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for &next in graph.get(&lock).into_iter().flatten() {
+            if let Entry::Vacant(entry) = parents.entry(next) {
+                entry.insert(lock);
+                stack.push(next);
+            }
+        }
+    }
+    None
+}
+
+/// Records that this thread now holds the lock `id`, unless its held locks
+/// have already been destroyed.
+#[cfg(feature = "enabled")]
+fn acquired(id: u64) {
+    let _ = HELD.try_with(|held| held.borrow_mut().push(id));
+}
+
+/// Perturbs the schedule while the lock `id` is still held, then records
+/// that this thread is about to release it.
+#[cfg(feature = "enabled")]
+fn releasing(id: u64) {
+    Perturbation::choose(SYNC_HOLD).apply();
+    let _ = HELD.try_with(|held| {
+        let mut held = held.borrow_mut();
+        if let Some(pos) = held.iter().rposition(|&held| held == id) {
+            held.remove(pos);
+        }
+    });
+}
+
+/// Returns true if a non-blocking acquisition should fail spuriously.
+#[cfg(feature = "enabled")]
+fn try_lock_failure() -> bool {
+    crate::trip_fault!(SYNC_TRY_LOCK_FAILURE, SYNC_TRY_LOCK_FAILURE.property())
+}
+
+/// Converts the guard within a [`LockResult`].
+#[cfg(feature = "enabled")]
+fn map_result<G, T>(result: LockResult<G>, f: impl FnOnce(G) -> T) -> LockResult<T> {
+    match result {
+        Ok(guard) => Ok(f(guard)),
+        Err(err) => Err(PoisonError::new(f(err.into_inner()))),
+    }
+}
+
+/// Converts the guard within a [`TryLockResult`].
+#[cfg(feature = "enabled")]
+fn map_try_result<G, T>(result: TryLockResult<G>, f: impl FnOnce(G) -> T) -> TryLockResult<T> {
+    match result {
+        Ok(guard) => Ok(f(guard)),
+        Err(TryLockError::Poisoned(err)) => Err(TryLockError::Poisoned(PoisonError::new(f(
+            err.into_inner()
+        )))),
+        Err(TryLockError::WouldBlock) => Err(TryLockError::WouldBlock),
+    }
+}
+
+#[cfg(all(test, feature = "enabled"))]
+mod tests {
+    use super::{LOCK_ORDER, LockId, Mutex, RwLock, SYNC_TRY_LOCK_FAILURE, TryLockError};
+    use crate::catalog::PRECEPT_CATALOG;
+
+    fn cycle_failures() -> usize {
+        PRECEPT_CATALOG
+            .iter()
+            .find(|entry| entry.property() == "precept sync: lock order cycle")
+            .map_or(0, |entry| entry.fail_count())
+    }
+
+    #[test]
+    fn lock_order_cycle_is_detected() {
+        let _lock = crate::fault::lock_catalog();
+        let _quiet = crate::fault::only_enable(&[]);
+        let (a, b) = (Mutex::new(1), RwLock::new(2));
+        let before = cycle_failures();
+
+        {
+            let _a = a.lock().unwrap();
+            let _b = b.read().unwrap();
+        }
+        {
+            let _b = b.write().unwrap();
+            let _a2 = a.try_lock().unwrap();
+        }
+        assert_eq!(cycle_failures(), before);
+
+        {
+            let _b = b.read().unwrap();
+            let _a = a.lock().unwrap();
+        }
+        assert_eq!(cycle_failures(), before + 1);
+    }
+
+    #[test]
+    fn dropped_locks_are_pruned() {
+        let (a, b) = (LockId::new(), LockId::new());
+        let (a_id, b_id) = (a.get(), b.get());
+        let graph = || LOCK_ORDER.lock().unwrap().clone();
+        LOCK_ORDER
+            .lock()
+            .unwrap()
+            .entry(a_id)
+            .or_default()
+            .insert(b_id);
+
+        drop(b);
+        assert!(graph().values().all(|edges| !edges.contains(&b_id)));
+        assert!(!graph().contains_key(&a_id));
+        drop(a);
+    }
+
+    #[test]
+    fn locks_work_in_thread_local_destructors() {
+        struct LocksOnDrop;
+
+        impl Drop for LocksOnDrop {
+            fn drop(&mut self) {
+                *Mutex::new(0).lock().unwrap() += 1;
+            }
+        }
+
+        thread_local! {
+            static LOCKS_ON_DROP: LocksOnDrop = const { LocksOnDrop };
+        }
+
+        // registered first, so destroyed after the held locks of the thread
+        std::thread::spawn(|| {
+            LOCKS_ON_DROP.with(|_| {});
+            *Mutex::new(0).lock().unwrap() += 1;
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn try_lock_fails_spuriously() {
+        let _lock = crate::fault::lock_catalog();
+        let _quiet = crate::fault::only_enable(&[]);
+        let mutex = Mutex::new(0);
+
+        let _forced = SYNC_TRY_LOCK_FAILURE.force_scoped(1);
+        assert!(matches!(mutex.try_lock(), Err(TryLockError::WouldBlock)));
+        *mutex.try_lock().unwrap() += 1;
+        assert_eq!(mutex.into_inner().unwrap(), 1);
+    }
+}
@@ -0,0 +1,620 @@
+//! Multi-producer single-consumer channels which delay, reorder, drop and
+//! duplicate messages.
+//!
+//! The channels mirror [`std::sync::mpsc`]. [`channel`] and [`sync_channel`]
+//! register their faults under a label naming the source location they were
+//! called from, such as `channel@src/main.rs:10:5`, while [`labeled_channel`]
+//! and [`labeled_sync_channel`] name their own:
+//!
+//! - `{label}.delay` holds back a message for up to 10ms before it can be
+//!   received. Messages sent after it may be received in the meantime.
+//! - `{label}.reorder` delivers a message ahead of up to
+//!   [`REORDER_WINDOW`]` - 1` messages sent before it.
+//!
+//! Channels created by [`lossy_channel`] additionally register:
+//!
+//! - `{label}.drop` silently drops a sent message.
+//! - `{label}.duplicate` delivers a sent message twice.
+//!
+//! Each fault is reported as a `precept_fault` event naming the channel label
+//! and the sequence number of the affected message.
+//!
+//! ```
+//! use precept::sync::channel;
+//!
+//! let (tx, rx) = channel::labeled_channel("requests");
+//! tx.send("ping").unwrap();
+//! assert_eq!(rx.recv(), Ok("ping"));
+//! ```
+//!
+//! When the `enabled` feature is off the constructors return the
+//! [`std::sync::mpsc`] types.
+
+pub use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+
+#[cfg(not(feature = "enabled"))]
+pub use std::sync::mpsc::{IntoIter, Iter, Receiver, Sender, SyncSender, TryIter};
+
+#[cfg(feature = "enabled")]
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "enabled")]
+use crate::fault::{FaultEntry, register};
+
+/// The number of deliverable messages a received message may be chosen from.
+pub const REORDER_WINDOW: usize = 8;
+
+/// The longest injected delivery delay.
+#[cfg(feature = "enabled")]
+const MAX_DELAY: Duration = Duration::from_millis(10);
+
+/// Creates an unbounded channel, mirroring [`std::sync::mpsc::channel`].
+///
+/// Its faults are registered under a label naming the caller's location, so
+/// channels created in different places fail independently.
+#[track_caller]
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    labeled_channel(&caller_label())
+}
+
+/// Creates a bounded channel, mirroring [`std::sync::mpsc::sync_channel`].
+///
+/// Its faults are registered under a label naming the caller's location, so
+/// channels created in different places fail independently.
+#[track_caller]
+pub fn sync_channel<T>(bound: usize) -> (SyncSender<T>, Receiver<T>) {
+    labeled_sync_channel(&caller_label(), bound)
+}
+
+/// Returns the label of a channel created at the caller's location.
+#[track_caller]
+fn caller_label() -> String {
+    let location = std::panic::Location::caller();
+    format!(
+        "channel@{}:{}:{}",
+        location.file(),
+        location.line(),
+        location.column()
+    )
+}
+
+/// Creates an unbounded channel whose faults are registered under `label`.
+#[cfg_attr(not(feature = "enabled"), allow(unused_variables))]
+pub fn labeled_channel<T>(label: &str) -> (Sender<T>, Receiver<T>) {
+    #[cfg(not(feature = "enabled"))]
+    return std::sync::mpsc::channel();
+
+    #[cfg(feature = "enabled")]
+    {
+        let (tx, rx) = mpsc::channel();
+        let faults = Arc::new(Faults::new(label, false));
+        (
+            Sender::new(tx, faults.clone(), None),
+            Receiver::new(rx, faults),
+        )
+    }
+}
+
+/// Creates a bounded channel whose faults are registered under `label`.
+#[cfg_attr(not(feature = "enabled"), allow(unused_variables))]
+pub fn labeled_sync_channel<T>(label: &str, bound: usize) -> (SyncSender<T>, Receiver<T>) {
+    #[cfg(not(feature = "enabled"))]
+    return std::sync::mpsc::sync_channel(bound);
+
+    #[cfg(feature = "enabled")]
+    {
+        let (tx, rx) = mpsc::sync_channel(bound);
+        let faults = Arc::new(Faults::new(label, false));
+        (
+            SyncSender::new(tx, faults.clone(), None),
+            Receiver::new(rx, faults),
+        )
+    }
+}
+
+/// Creates an unbounded channel whose faults are registered under `label`,
+/// which also drops and duplicates messages.
+#[cfg_attr(not(feature = "enabled"), allow(unused_variables))]
+pub fn lossy_channel<T: Clone>(label: &str) -> (Sender<T>, Receiver<T>) {
+    #[cfg(not(feature = "enabled"))]
+    return std::sync::mpsc::channel();
+
+    #[cfg(feature = "enabled")]
+    {
+        let (tx, rx) = mpsc::channel();
+        let faults = Arc::new(Faults::new(label, true));
+        (
+            Sender::new(tx, faults.clone(), Some(T::clone)),
+            Receiver::new(rx, faults),
+        )
+    }
+}
+
+#[cfg(feature = "enabled")]
+#[derive(Debug)]
+struct Faults {
+    label: String,
+    delay: &'static FaultEntry,
+    reorder: &'static FaultEntry,
+    drop: Option<&'static FaultEntry>,
+    duplicate: Option<&'static FaultEntry>,
+    next_seq: AtomicU64,
+}
+
+#[cfg(feature = "enabled")]
+impl Faults {
+    fn new(label: &str, lossy: bool) -> Self {
+        let lossy_fault = |name: &str| lossy.then(|| register(&format!("{label}.{name}")));
+        Self {
+            label: label.to_owned(),
+            delay: register(&format!("{label}.delay")),
+            reorder: register(&format!("{label}.reorder")),
+            drop: lossy_fault("drop"),
+            duplicate: lossy_fault("duplicate"),
+            next_seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Trips `fault`, emitting a `precept_fault` event naming the message
+    /// `seq` when it trips.
+    #[track_caller]
+    fn trip(&self, fault: &'static FaultEntry, seq: u64) -> bool {
+        let tripped = fault.trip();
+        if tripped {
+            crate::emit_event!("precept_fault", {
+                "name": fault.name(),
+                "details": { "label": self.label, "seq": seq },
+            });
+        }
+        tripped
+    }
+
+    /// Wraps `value` for sending, returning no envelopes if it is dropped and
+    /// two if it is duplicated.
+    #[track_caller]
+    fn envelopes<T>(&self, value: T, duplicate: Option<fn(&T) -> T>) -> Vec<Envelope<T>> {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        if matches!(self.drop, Some(fault) if self.trip(fault, seq)) {
+            return vec![];
+        }
+        let deliver_at = self.trip(self.delay, seq).then(|| {
+            let max = u64::try_from(MAX_DELAY.as_nanos()).unwrap_or(u64::MAX);
+            Instant::now()
+                + Duration::from_nanos(crate::random::range_for(self.delay.name(), 1..=max))
+        });
+        let mut envelopes = Vec::with_capacity(2);
+        if let Some(duplicate) = duplicate {
+            if matches!(self.duplicate, Some(fault) if self.trip(fault, seq)) {
+                envelopes.push(Envelope {
+                    value: duplicate(&value),
+                    seq,
+                    deliver_at,
+                });
+            }
+        }
+        envelopes.push(Envelope { value, seq, deliver_at });
+        envelopes
+    }
+}
+
+#[cfg(feature = "enabled")]
+struct Envelope<T> {
+    value: T,
+    seq: u64,
+    /// When the message may be received, if its delivery is delayed.
+    deliver_at: Option<Instant>,
+}
+
+/// The sending half of an unbounded channel, mirroring
+/// [`std::sync::mpsc::Sender`].
+#[cfg(feature = "enabled")]
+pub struct Sender<T> {
+    inner: mpsc::Sender<Envelope<T>>,
+    faults: Arc<Faults>,
+    duplicate: Option<fn(&T) -> T>,
+}
+
+#[cfg(feature = "enabled")]
+impl<T> Sender<T> {
+    fn new(
+        inner: mpsc::Sender<Envelope<T>>,
+        faults: Arc<Faults>,
+        duplicate: Option<fn(&T) -> T>,
+    ) -> Self {
+        Self { inner, faults, duplicate }
+    }
+
+    /// Sends a message on this channel, failing if the receiver has been
+    /// dropped.
+    ///
+    /// A message dropped by a lossy channel is reported as sent.
+    #[track_caller]
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        for envelope in self.faults.envelopes(value, self.duplicate) {
+            self.inner
+                .send(envelope)
+                .map_err(|err| SendError(err.0.value))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "enabled")]
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone(), self.faults.clone(), self.duplicate)
+    }
+}
+
+#[cfg(feature = "enabled")]
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("label", &self.faults.label)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The sending half of a bounded channel, mirroring
+/// [`std::sync::mpsc::SyncSender`].
+#[cfg(feature = "enabled")]
+pub struct SyncSender<T> {
+    inner: mpsc::SyncSender<Envelope<T>>,
+    faults: Arc<Faults>,
+    duplicate: Option<fn(&T) -> T>,
+}
+
+#[cfg(feature = "enabled")]
+impl<T> SyncSender<T> {
+    fn new(
+        inner: mpsc::SyncSender<Envelope<T>>,
+        faults: Arc<Faults>,
+        duplicate: Option<fn(&T) -> T>,
+    ) -> Self {
+        Self { inner, faults, duplicate }
+    }
+
+    /// Sends a message on this channel, blocking while the channel is full.
+    #[track_caller]
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        for envelope in self.faults.envelopes(value, self.duplicate) {
+            self.inner
+                .send(envelope)
+                .map_err(|err| SendError(err.0.value))?;
+        }
+        Ok(())
+    }
+
+    /// Attempts to send a message on this channel without blocking.
+    #[track_caller]
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        for envelope in self.faults.envelopes(value, self.duplicate) {
+            self.inner.try_send(envelope).map_err(|err| match err {
+                TrySendError::Full(envelope) => TrySendError::Full(envelope.value),
+                TrySendError::Disconnected(envelope) => TrySendError::Disconnected(envelope.value),
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "enabled")]
+impl<T> Clone for SyncSender<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone(), self.faults.clone(), self.duplicate)
+    }
+}
+
+#[cfg(feature = "enabled")]
+impl<T> fmt::Debug for SyncSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncSender")
+            .field("label", &self.faults.label)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The receiving half of a channel, mirroring
+/// [`std::sync::mpsc::Receiver`].
+#[cfg(feature = "enabled")]
+pub struct Receiver<T> {
+    inner: mpsc::Receiver<Envelope<T>>,
+    faults: Arc<Faults>,
+    /// Messages taken from the channel but not yet received, in send order.
+    buffer: RefCell<VecDeque<Envelope<T>>>,
+    /// Whether every sender has been dropped.
+    disconnected: Cell<bool>,
+}
+
+#[cfg(feature = "enabled")]
+impl<T> Receiver<T> {
+    fn new(inner: mpsc::Receiver<Envelope<T>>, faults: Arc<Faults>) -> Self {
+        Self {
+            inner,
+            faults,
+            buffer: RefCell::new(VecDeque::with_capacity(REORDER_WINDOW)),
+            disconnected: Cell::new(false),
+        }
+    }
+
+    /// Moves every message waiting in the channel into the buffer.
+    fn drain(&self, buffer: &mut VecDeque<Envelope<T>>) {
+        loop {
+            match self.inner.try_recv() {
+                Ok(envelope) => buffer.push_back(envelope),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.disconnected.set(true);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Receives the next message, waiting until `deadline` if one is given.
+    #[track_caller]
+    fn receive(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        let mut buffer = self.buffer.borrow_mut();
+        loop {
+            self.drain(&mut buffer);
+            let now = Instant::now();
+            let deliverable: Vec<usize> = (0..buffer.len())
+                .filter(|&i| buffer[i].deliver_at.is_none_or(|at| at <= now))
+                .take(REORDER_WINDOW)
+                .collect();
+            if let Some(&first) = deliverable.first() {
+                let mut index = first;
+                if deliverable.len() > 1 {
+                    let reorder = self.faults.reorder.name();
+                    let candidate = deliverable
+                        [crate::random::range_for(reorder, 1..deliverable.len() as u64) as usize];
+                    if self.faults.trip(self.faults.reorder, buffer[candidate].seq) {
+                        index = candidate;
+                    }
+                }
+                let envelope = buffer.remove(index).expect("index is within the buffer");
+                return Ok(envelope.value);
+            }
+
+            // wait for the next delayed message to become deliverable, a new
+            // message to arrive or the deadline to pass
+            let next_delivery = buffer
+                .iter()
+                .filter_map(|envelope| envelope.deliver_at)
+                .min();
+            if self.disconnected.get() && next_delivery.is_none() {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            if deadline.is_some_and(|deadline| deadline <= now) {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            let wake = match (next_delivery, deadline) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            if self.disconnected.get() {
+                // only delayed messages remain, so nothing else can arrive
+                thread::sleep(wake.map_or(Duration::ZERO, |wake| wake - now));
+                continue;
+            }
+            let received = match wake {
+                None => self
+                    .inner
+                    .recv()
+                    .map_err(|RecvError| RecvTimeoutError::Disconnected),
+                Some(wake) => self.inner.recv_timeout(wake - now),
+            };
+            match received {
+                Ok(envelope) => buffer.push_back(envelope),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => self.disconnected.set(true),
+            }
+        }
+    }
+
+    /// Blocks until a message is received, failing once every sender has
+    /// been dropped and the channel is empty.
+    #[track_caller]
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.receive(None).map_err(|_| RecvError)
+    }
+
+    /// Attempts to receive a message without blocking.
+    ///
+    /// A delayed message can't be received until its delay has passed.
+    #[track_caller]
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.receive(Some(Instant::now())).map_err(|err| match err {
+            RecvTimeoutError::Timeout => TryRecvError::Empty,
+            RecvTimeoutError::Disconnected => TryRecvError::Disconnected,
+        })
+    }
+
+    /// Blocks until a message is received or `timeout` elapses.
+    #[track_caller]
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.receive(Some(deadline)),
+            None => self.receive(None),
+        }
+    }
+
+    /// Returns an iterator which blocks waiting for messages until every
+    /// sender has been dropped.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { rx: self }
+    }
+
+    /// Returns an iterator over the messages which can be received without
+    /// blocking.
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        TryIter { rx: self }
+    }
+}
+
+#[cfg(feature = "enabled")]
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("label", &self.faults.label)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An iterator over messages on a [`Receiver`], created by
+/// [`Receiver::iter`].
+#[cfg(feature = "enabled")]
+#[derive(Debug)]
+pub struct Iter<'a, T> {
+    rx: &'a Receiver<T>,
+}
+
+#[cfg(feature = "enabled")]
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+/// An iterator over the messages which can be received without blocking,
+/// created by [`Receiver::try_iter`].
+#[cfg(feature = "enabled")]
+#[derive(Debug)]
+pub struct TryIter<'a, T> {
+    rx: &'a Receiver<T>,
+}
+
+#[cfg(feature = "enabled")]
+impl<T> Iterator for TryIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.try_recv().ok()
+    }
+}
+
+/// An owning iterator over messages on a [`Receiver`].
+#[cfg(feature = "enabled")]
+#[derive(Debug)]
+pub struct IntoIter<T> {
+    rx: Receiver<T>,
+}
+
+#[cfg(feature = "enabled")]
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+#[cfg(feature = "enabled")]
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { rx: self }
+    }
+}
+
+#[cfg(feature = "enabled")]
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(all(test, feature = "enabled"))]
+mod tests {
+    use std::time::Duration;
+
+    use super::{TryRecvError, labeled_channel, lossy_channel};
+    use crate::fault::get_fault_by_name;
+
+    fn force(name: &str) -> crate::fault::FaultGuard<'static> {
+        get_fault_by_name(name).unwrap().force_scoped(1)
+    }
+
+    #[test]
+    fn unlabeled_channels_fail_independently() {
+        let _lock = crate::fault::lock_catalog();
+        let (_tx, _rx) = super::channel::<u8>();
+        let first = line!() - 1;
+        let (_tx, _rx) = super::sync_channel::<u8>(1);
+        let second = line!() - 1;
+
+        for line in [first, second] {
+            let prefix = format!("channel@{}:{line}:", file!());
+            let delay = crate::fault::all_faults().find(|fault| {
+                fault.name().starts_with(&prefix) && fault.name().ends_with(".delay")
+            });
+            assert!(delay.is_some(), "no faults registered for line {line}");
+        }
+    }
+
+    #[test]
+    fn channel_faults() {
+        let _lock = crate::fault::lock_catalog();
+        let (tx, rx) = lossy_channel("channel_test");
+        let _quiet = crate::fault::only_enable(&[]);
+
+        let _forced = force("channel_test.drop");
+        tx.send(1).unwrap();
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        let _forced = force("channel_test.duplicate");
+        tx.send(2).unwrap();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [2, 2]);
+
+        let _forced = force("channel_test.delay");
+        tx.send(3).unwrap();
+        assert_eq!(rx.recv(), Ok(3));
+
+        for value in 4..8 {
+            tx.send(value).unwrap();
+        }
+        let _forced = force("channel_test.reorder");
+        assert_ne!(rx.recv(), Ok(4));
+        let mut rest: Vec<_> = rx.try_iter().collect();
+        rest.sort();
+        assert_eq!(rest.len(), 3);
+
+        drop(tx);
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn delays_apply_per_message() {
+        let _lock = crate::fault::lock_catalog();
+        let (tx, rx) = labeled_channel("channel_delay_test");
+        let _quiet = crate::fault::only_enable(&[]);
+
+        let _forced = force("channel_delay_test.delay");
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        drop(tx);
+        assert_eq!(rx.recv_timeout(Duration::from_secs(1)), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+}
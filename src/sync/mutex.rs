@@ -0,0 +1,135 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    sync as std_sync,
+};
+
+use super::{
+    LockId, LockResult, TryLockError, TryLockResult, acquired, before_acquire, map_result,
+    map_try_result, releasing, try_lock_failure,
+};
+
+/// A mutual exclusion primitive mirroring [`std::sync::Mutex`].
+///
+/// See the [module docs](super) for the injected perturbations.
+#[derive(Default)]
+pub struct Mutex<T: ?Sized> {
+    id: LockId,
+    inner: std_sync::Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    /// Creates a new unlocked mutex.
+    #[inline]
+    pub const fn new(value: T) -> Self {
+        Self {
+            id: LockId::new(),
+            inner: std_sync::Mutex::new(value),
+        }
+    }
+
+    /// Consumes this mutex, returning the underlying data.
+    #[inline]
+    pub fn into_inner(self) -> LockResult<T> {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Acquires the mutex, blocking the current thread until it is able to do
+    /// so.
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        let id = self.id.get();
+        before_acquire(id);
+        let result = self.inner.lock();
+        acquired(id);
+        map_result(result, |inner| MutexGuard { inner, id })
+    }
+
+    /// Attempts to acquire the mutex without blocking.
+    ///
+    /// May fail spuriously with [`TryLockError::WouldBlock`].
+    pub fn try_lock(&self) -> TryLockResult<MutexGuard<'_, T>> {
+        if try_lock_failure() {
+            return Err(TryLockError::WouldBlock);
+        }
+        let id = self.id.get();
+        let result = self.inner.try_lock();
+        if !matches!(result, Err(TryLockError::WouldBlock)) {
+            acquired(id);
+        }
+        map_try_result(result, |inner| MutexGuard { inner, id })
+    }
+
+    /// Returns true if the mutex is poisoned.
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    /// Clears the poisoned state of the mutex.
+    #[inline]
+    pub fn clear_poison(&self) {
+        self.inner.clear_poison();
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    #[inline]
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.inner.get_mut()
+    }
+}
+
+impl<T> From<T> for Mutex<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+/// A guard releasing a [`Mutex`] when dropped, mirroring
+/// [`std::sync::MutexGuard`].
+#[must_use = "if unused the Mutex will immediately unlock"]
+pub struct MutexGuard<'a, T: ?Sized + 'a> {
+    inner: std_sync::MutexGuard<'a, T>,
+    id: u64,
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        releasing(self.id);
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for MutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for MutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
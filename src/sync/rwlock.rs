@@ -0,0 +1,196 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    sync as std_sync,
+};
+
+use super::{
+    LockId, LockResult, TryLockError, TryLockResult, acquired, before_acquire, map_result,
+    map_try_result, releasing, try_lock_failure,
+};
+
+/// A reader-writer lock mirroring [`std::sync::RwLock`].
+///
+/// Read and write acquisitions are tracked as the same lock in the lock-order
+/// graph. See the [module docs](super) for the injected perturbations.
+#[derive(Default)]
+pub struct RwLock<T: ?Sized> {
+    id: LockId,
+    inner: std_sync::RwLock<T>,
+}
+
+impl<T> RwLock<T> {
+    /// Creates a new unlocked reader-writer lock.
+    #[inline]
+    pub const fn new(value: T) -> Self {
+        Self {
+            id: LockId::new(),
+            inner: std_sync::RwLock::new(value),
+        }
+    }
+
+    /// Consumes this lock, returning the underlying data.
+    #[inline]
+    pub fn into_inner(self) -> LockResult<T> {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Acquires shared read access, blocking the current thread until it is
+    /// able to do so.
+    pub fn read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        let id = self.id.get();
+        before_acquire(id);
+        let result = self.inner.read();
+        acquired(id);
+        map_result(result, |inner| RwLockReadGuard { inner, id })
+    }
+
+    /// Attempts to acquire shared read access without blocking.
+    ///
+    /// May fail spuriously with [`TryLockError::WouldBlock`].
+    pub fn try_read(&self) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        if try_lock_failure() {
+            return Err(TryLockError::WouldBlock);
+        }
+        let id = self.id.get();
+        let result = self.inner.try_read();
+        if !matches!(result, Err(TryLockError::WouldBlock)) {
+            acquired(id);
+        }
+        map_try_result(result, |inner| RwLockReadGuard { inner, id })
+    }
+
+    /// Acquires exclusive write access, blocking the current thread until it
+    /// is able to do so.
+    pub fn write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        let id = self.id.get();
+        before_acquire(id);
+        let result = self.inner.write();
+        acquired(id);
+        map_result(result, |inner| RwLockWriteGuard { inner, id })
+    }
+
+    /// Attempts to acquire exclusive write access without blocking.
+    ///
+    /// May fail spuriously with [`TryLockError::WouldBlock`].
+    pub fn try_write(&self) -> TryLockResult<RwLockWriteGuard<'_, T>> {
+        if try_lock_failure() {
+            return Err(TryLockError::WouldBlock);
+        }
+        let id = self.id.get();
+        let result = self.inner.try_write();
+        if !matches!(result, Err(TryLockError::WouldBlock)) {
+            acquired(id);
+        }
+        map_try_result(result, |inner| RwLockWriteGuard { inner, id })
+    }
+
+    /// Returns true if the lock is poisoned.
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    /// Clears the poisoned state of the lock.
+    #[inline]
+    pub fn clear_poison(&self) {
+        self.inner.clear_poison();
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    #[inline]
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.inner.get_mut()
+    }
+}
+
+impl<T> From<T> for RwLock<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+/// A guard releasing shared read access to a [`RwLock`] when dropped,
+/// mirroring [`std::sync::RwLockReadGuard`].
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    inner: std_sync::RwLockReadGuard<'a, T>,
+    id: u64,
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        releasing(self.id);
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for RwLockReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+/// A guard releasing exclusive write access to a [`RwLock`] when dropped,
+/// mirroring [`std::sync::RwLockWriteGuard`].
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    inner: std_sync::RwLockWriteGuard<'a, T>,
+    id: u64,
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        releasing(self.id);
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for RwLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
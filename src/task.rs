@@ -0,0 +1,157 @@
+//! Perturbation of async tasks which shakes out bugs that depend on when a
+//! future returns [`Poll::Pending`].
+//!
+//! [`perturb`] and [`FutureExt::perturb`] wrap a future so that, before each
+//! poll is delegated to it, the wrapper may return [`Poll::Pending`]:
+//!
+//! - `{label}.pending` returns a spurious `Pending`, immediately waking the
+//!   task.
+//! - `{label}.delayed_wake` returns `Pending` and wakes the task after a
+//!   random delay of up to 10ms.
+//!
+//! Each perturbation is reported as a `precept_fault` event.
+//!
+//! ```
+//! use precept::task::FutureExt;
+//!
+//! async fn commit() -> u64 {
+//!     let flushed = async { 42 }.perturb("flush").await;
+//!     flushed + 1
+//! }
+//! # let _ = commit();
+//! ```
+//!
+//! When the `enabled` feature is off the wrapped future is returned as is.
+//!
+//! [`Poll::Pending`]: std::task::Poll::Pending
+
+use std::future::Future;
+
+#[cfg(feature = "enabled")]
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "enabled")]
+use crate::fault::{FaultEntry, LatencyDistribution, register};
+
+/// The maximum delay before a perturbed task is woken.
+#[cfg(feature = "enabled")]
+const MAX_DELAY: Duration = Duration::from_millis(10);
+
+/// Wraps `future` so that it may spuriously return
+/// [`Pending`](std::task::Poll::Pending), using faults registered under
+/// `label`.
+///
+/// See the [module docs](self) for the injected perturbations.
+#[cfg_attr(not(feature = "enabled"), allow(unused_variables))]
+#[inline]
+pub fn perturb<F: Future>(future: F, label: &str) -> impl Future<Output = F::Output> {
+    #[cfg(not(feature = "enabled"))]
+    return future;
+
+    #[cfg(feature = "enabled")]
+    Perturbed {
+        future,
+        pending: register(&format!("{label}.pending")),
+        delayed_wake: register(&format!("{label}.delayed_wake")),
+        deadline: None,
+    }
+}
+
+/// Extension methods for futures.
+pub trait FutureExt: Future + Sized {
+    /// Wraps this future so that it may spuriously return [`Poll::Pending`].
+    /// See [`perturb`].
+    ///
+    /// [`Poll::Pending`]: std::task::Poll::Pending
+    #[inline]
+    fn perturb(self, label: &str) -> impl Future<Output = Self::Output> {
+        perturb(self, label)
+    }
+}
+
+impl<F: Future> FutureExt for F {}
+
+/// Future returned by [`perturb`] when the `enabled` feature is on.
+#[cfg(feature = "enabled")]
+struct Perturbed<F> {
+    future: F,
+    pending: &'static FaultEntry,
+    delayed_wake: &'static FaultEntry,
+    /// When a delayed wake is due, while the task is waiting for one.
+    deadline: Option<Instant>,
+}
+
+#[cfg(feature = "enabled")]
+impl<F: Future> Future for Perturbed<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        // SAFETY: `future` is structurally pinned. It is never moved out of
+        // `Perturbed` and `Perturbed` has no `Drop` impl.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        if let Some(deadline) = this.deadline {
+            if Instant::now() < deadline {
+                crate::timer::wake_at(deadline, cx.waker().clone());
+                return Poll::Pending;
+            }
+            // let the delayed poll proceed without another roll
+            this.deadline = None;
+            return future.poll(cx);
+        }
+        if this.pending.trip() {
+            crate::emit_event!("precept_fault", { "name": this.pending.name(), "details": null });
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        if let Some(delay) = this
+            .delayed_wake
+            .trip_latency(MAX_DELAY, LatencyDistribution::default())
+        {
+            crate::emit_event!("precept_fault", {
+                "name": this.delayed_wake.name(),
+                "details": { "delay_us": u64::try_from(delay.as_micros()).unwrap_or(u64::MAX) },
+            });
+            let deadline = Instant::now() + delay;
+            this.deadline = Some(deadline);
+            crate::timer::wake_at(deadline, cx.waker().clone());
+            return Poll::Pending;
+        }
+        future.poll(cx)
+    }
+}
+
+#[cfg(all(test, feature = "enabled"))]
+mod tests {
+    use std::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use super::{FutureExt, perturb};
+    use crate::{fault::get_fault_by_name, timer::tests::block_on};
+
+    #[test]
+    fn perturbed_futures_return_pending() {
+        let _lock = crate::fault::lock_catalog();
+        let mut future = pin!(perturb(async { 7 }, "task_test"));
+        let _quiet = crate::fault::only_enable(&[]);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let _forced = get_fault_by_name("task_test.pending")
+            .unwrap()
+            .force_scoped(1);
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Ready(7));
+
+        let delayed_wake = get_fault_by_name("task_test.delayed_wake").unwrap();
+        let _forced = delayed_wake.force_scoped(1);
+        assert_eq!(block_on(async { 8 }.perturb("task_test")), 8);
+        assert_eq!(delayed_wake.count_pending(), 0);
+    }
+}
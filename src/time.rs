@@ -0,0 +1,372 @@
+//! Simulated time which can be fast-forwarded by a test harness or guidance
+//! system.
+//!
+//! [`Instant`] and [`SystemTime`] mirror their [`std::time`] counterparts. When
+//! the `enabled` feature is on they read a virtual clock which runs at the
+//! speed of the real clock plus an offset. The offset only grows, via
+//! [`advance`] or the `time.jump` fault, so timeouts and lease expirations
+//! can be tested without waiting for them in real time.
+//!
+//! [`sleep`] waits on the virtual clock, so advancing the clock wakes
+//! sleepers early. When the [`TIME_JUMP`] fault trips, a sleep instead jumps
+//! the clock past its deadline by a random amount.
+//!
+//! [`skewed_now`] reads the wall clock as seen by a labeled node, injecting
+//! per-node clock anomalies: backwards and forward jumps, drift and freezes.
+//!
+//! When the `enabled` feature is off every function delegates to
+//! [`std::time`] and [`std::thread::sleep`].
+//!
+//! ```
+//! use std::time::Duration;
+//! use precept::time::{self, Instant};
+//!
+//! let lease_expiry = Instant::now() + Duration::from_secs(30);
+//! time::advance(Duration::from_secs(60));
+//! # #[cfg(feature = "enabled")]
+//! assert!(Instant::now() > lease_expiry);
+//! ```
+
+use std::{
+    fmt,
+    ops::{Add, AddAssign, Sub, SubAssign},
+    time::{self as std_time, Duration, SystemTimeError},
+};
+
+#[cfg(feature = "enabled")]
+use std::sync::{
+    Condvar, Mutex, PoisonError,
+    atomic::{AtomicU64, Ordering},
+};
+
+mod skew;
+
+pub use skew::{reset_skew, skewed_now};
+
+crate::declare_fault! {
+    /// Makes a [`sleep`] jump the virtual clock past its deadline by up to the
+    /// length of the sleep.
+    pub TIME_JUMP = "time.jump";
+}
+
+/// The virtual clock's offset from the real clock, in nanoseconds.
+#[cfg(feature = "enabled")]
+static OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// Guards changes to [`OFFSET`] so that sleepers can wait for them.
+#[cfg(feature = "enabled")]
+static CLOCK: Mutex<()> = Mutex::new(());
+
+#[cfg(feature = "enabled")]
+static CLOCK_ADVANCED: Condvar = Condvar::new();
+
+/// Returns how far the virtual clock is ahead of the real clock.
+///
+/// Always zero when the `enabled` feature is off.
+#[inline]
+pub fn offset() -> Duration {
+    #[cfg(feature = "enabled")]
+    return Duration::from_nanos(OFFSET.load(Ordering::Acquire));
+    #[cfg(not(feature = "enabled"))]
+    Duration::ZERO
+}
+
+/// Moves the virtual clock forward by `duration`, waking any [`sleep`] whose
+/// deadline has passed.
+///
+/// Each advance is reported as a `precept_time_advance` event. Has no effect
+/// when the `enabled` feature is off.
+#[cfg_attr(not(feature = "enabled"), allow(unused_variables))]
+pub fn advance(duration: Duration) {
+    #[cfg(feature = "enabled")]
+    {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        let guard = CLOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let offset = OFFSET.load(Ordering::Acquire).saturating_add(nanos);
+        OFFSET.store(offset, Ordering::Release);
+        drop(guard);
+        CLOCK_ADVANCED.notify_all();
+        crate::dispatch::emit(crate::dispatch::Event::Custom {
+            name: "precept_time_advance",
+            value: serde_json::json!({
+                "advance_us": u64::try_from(duration.as_micros()).unwrap_or(u64::MAX),
+                "offset_us": offset / 1_000,
+            }),
+        });
+    }
+}
+
+/// Returns the current time on the virtual clock.
+#[inline]
+pub fn now() -> Instant {
+    Instant::now()
+}
+
+/// Blocks the current thread until `duration` has elapsed on the virtual
+/// clock.
+pub fn sleep(duration: Duration) {
+    #[cfg(not(feature = "enabled"))]
+    std::thread::sleep(duration);
+
+    #[cfg(feature = "enabled")]
+    {
+        // a deadline beyond the range of the clock is never reached
+        let deadline = now().checked_add(duration);
+        crate::sometimes_fault!(
+            TIME_JUMP,
+            advance(overshoot(duration)),
+            { "sleep_us": u64::try_from(duration.as_micros()).unwrap_or(u64::MAX) }
+        );
+        let mut guard = CLOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            let now = now();
+            let timeout = match deadline {
+                Some(deadline) if now >= deadline => break,
+                Some(deadline) => deadline - now,
+                None => Duration::MAX,
+            };
+            guard = CLOCK_ADVANCED
+                .wait_timeout(guard, timeout)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+}
+
+/// Returns `duration` plus a random overshoot of up to `duration`, the jump
+/// taken by a sleep when [`TIME_JUMP`] trips.
+#[cfg(feature = "enabled")]
+fn overshoot(duration: Duration) -> Duration {
+    let max = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+    let jump = crate::random::range_for(TIME_JUMP.name(), 0..=max);
+    duration.saturating_add(Duration::from_nanos(jump))
+}
+
+/// A measurement of the virtual monotonic clock, mirroring
+/// [`std::time::Instant`].
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(std_time::Instant);
+
+impl Instant {
+    /// Returns the current time on the virtual clock.
+    #[inline]
+    pub fn now() -> Self {
+        Self(std_time::Instant::now() + offset())
+    }
+
+    /// Returns the amount of time elapsed from `earlier` to this instant, or
+    /// zero if `earlier` is later.
+    #[inline]
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        self.0.saturating_duration_since(earlier.0)
+    }
+
+    /// Returns the amount of time elapsed from `earlier` to this instant, or
+    /// `None` if `earlier` is later.
+    #[inline]
+    pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+        self.0.checked_duration_since(earlier.0)
+    }
+
+    /// Returns the amount of time elapsed from `earlier` to this instant, or
+    /// zero if `earlier` is later.
+    #[inline]
+    pub fn saturating_duration_since(&self, earlier: Instant) -> Duration {
+        self.0.saturating_duration_since(earlier.0)
+    }
+
+    /// Returns the amount of virtual time elapsed since this instant.
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+
+    /// Returns `self + duration`, or `None` on overflow.
+    #[inline]
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        self.0.checked_add(duration).map(Self)
+    }
+
+    /// Returns `self - duration`, or `None` on overflow.
+    #[inline]
+    pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+        self.0.checked_sub(duration).map(Self)
+    }
+}
+
+impl fmt::Debug for Instant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    #[inline]
+    fn add(self, rhs: Duration) -> Instant {
+        Self(self.0 + rhs)
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    #[inline]
+    fn add_assign(&mut self, rhs: Duration) {
+        self.0 += rhs;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    #[inline]
+    fn sub(self, rhs: Duration) -> Instant {
+        Self(self.0 - rhs)
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    #[inline]
+    fn sub_assign(&mut self, rhs: Duration) {
+        self.0 -= rhs;
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    #[inline]
+    fn sub(self, rhs: Instant) -> Duration {
+        self.duration_since(rhs)
+    }
+}
+
+/// A measurement of the virtual wall clock, mirroring
+/// [`std::time::SystemTime`].
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemTime(std_time::SystemTime);
+
+impl SystemTime {
+    /// The Unix epoch, 1970-01-01 00:00:00 UTC.
+    pub const UNIX_EPOCH: SystemTime = SystemTime(std_time::UNIX_EPOCH);
+
+    /// Returns the current time on the virtual wall clock.
+    #[inline]
+    pub fn now() -> Self {
+        Self(std_time::SystemTime::now() + offset())
+    }
+
+    /// Returns the amount of time elapsed from `earlier` to this time, or an
+    /// error if `earlier` is later.
+    #[inline]
+    pub fn duration_since(&self, earlier: SystemTime) -> Result<Duration, SystemTimeError> {
+        self.0.duration_since(earlier.0)
+    }
+
+    /// Returns the amount of virtual time elapsed since this time, or an
+    /// error if the virtual wall clock is behind it.
+    #[inline]
+    pub fn elapsed(&self) -> Result<Duration, SystemTimeError> {
+        Self::now().duration_since(*self)
+    }
+
+    /// Returns `self + duration`, or `None` on overflow.
+    #[inline]
+    pub fn checked_add(&self, duration: Duration) -> Option<SystemTime> {
+        self.0.checked_add(duration).map(Self)
+    }
+
+    /// Returns `self - duration`, or `None` on overflow.
+    #[inline]
+    pub fn checked_sub(&self, duration: Duration) -> Option<SystemTime> {
+        self.0.checked_sub(duration).map(Self)
+    }
+}
+
+/// The Unix epoch, 1970-01-01 00:00:00 UTC.
+pub const UNIX_EPOCH: SystemTime = SystemTime::UNIX_EPOCH;
+
+impl fmt::Debug for SystemTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<SystemTime> for std_time::SystemTime {
+    #[inline]
+    fn from(time: SystemTime) -> Self {
+        time.0
+    }
+}
+
+impl Add<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    #[inline]
+    fn add(self, rhs: Duration) -> SystemTime {
+        Self(self.0 + rhs)
+    }
+}
+
+impl AddAssign<Duration> for SystemTime {
+    #[inline]
+    fn add_assign(&mut self, rhs: Duration) {
+        self.0 += rhs;
+    }
+}
+
+impl Sub<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    #[inline]
+    fn sub(self, rhs: Duration) -> SystemTime {
+        Self(self.0 - rhs)
+    }
+}
+
+impl SubAssign<Duration> for SystemTime {
+    #[inline]
+    fn sub_assign(&mut self, rhs: Duration) {
+        self.0 -= rhs;
+    }
+}
+
+#[cfg(all(test, feature = "enabled"))]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::{Instant, SystemTime, TIME_JUMP, advance, sleep};
+
+    #[test]
+    fn advance_moves_both_clocks() {
+        let _lock = crate::fault::lock_catalog();
+        let (instant, system) = (Instant::now(), SystemTime::now());
+        advance(Duration::from_secs(3600));
+        assert!(instant.elapsed() >= Duration::from_secs(3600));
+        assert!(system.elapsed().unwrap() >= Duration::from_secs(3600));
+
+        // a time jump past the longest sleep saturates rather than overflowing
+        assert_eq!(super::overshoot(Duration::MAX), Duration::MAX);
+    }
+
+    #[test]
+    fn sleep_wakes_when_clock_advances() {
+        let _lock = crate::fault::lock_catalog();
+        let _quiet = TIME_JUMP.disable_scoped();
+        let real = std::time::Instant::now();
+
+        let advancer = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(20));
+            advance(Duration::from_secs(600));
+        });
+        sleep(Duration::from_secs(300));
+        advancer.join().unwrap();
+        assert!(real.elapsed() < Duration::from_secs(60));
+
+        let _forced = TIME_JUMP.force_scoped(1);
+        let start = Instant::now();
+        sleep(Duration::from_secs(300));
+        assert!(start.elapsed() >= Duration::from_secs(300));
+        assert!(real.elapsed() < Duration::from_secs(60));
+    }
+}
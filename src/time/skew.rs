@@ -0,0 +1,225 @@
+//! Per-node wall clock anomalies.
+
+use super::SystemTime;
+
+#[cfg(feature = "enabled")]
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+#[cfg(feature = "enabled")]
+use super::Instant;
+#[cfg(feature = "enabled")]
+use crate::fault::{FaultEntry, register};
+
+/// The range of backwards jumps, in nanoseconds.
+#[cfg(feature = "enabled")]
+const BACKWARD_JUMP: (u64, u64) = (1_000_000, 5_000_000_000);
+
+/// The range of forward jumps, in nanoseconds.
+#[cfg(feature = "enabled")]
+const FORWARD_JUMP: (u64, u64) = (1_000_000_000, 120_000_000_000);
+
+/// The largest drift rate, in parts per million.
+#[cfg(feature = "enabled")]
+const MAX_DRIFT_PPM: u64 = 1_000;
+
+/// The range of freeze durations, in nanoseconds.
+#[cfg(feature = "enabled")]
+const FREEZE: (u64, u64) = (10_000_000, 5_000_000_000);
+
+/// Returns a random value in the inclusive range `(min, max)`, drawn for
+/// `fault`.
+#[cfg(feature = "enabled")]
+fn random_in(fault: &FaultEntry, (min, max): (u64, u64)) -> u64 {
+    crate::random::range_for(fault.name(), min..=max)
+}
+
+#[cfg(feature = "enabled")]
+struct NodeClock {
+    backward_jump: &'static FaultEntry,
+    forward_jump: &'static FaultEntry,
+    drift: &'static FaultEntry,
+    freeze: &'static FaultEntry,
+    /// The accumulated offset from the virtual wall clock, in nanoseconds.
+    offset: i128,
+    /// The current drift rate, in parts per million.
+    drift_ppm: i64,
+    /// When the current drift rate took effect.
+    drift_start: Instant,
+    /// The reading returned while frozen, and when the freeze ends.
+    frozen: Option<(SystemTime, Instant)>,
+}
+
+#[cfg(feature = "enabled")]
+impl NodeClock {
+    fn new(node: &str) -> Self {
+        Self {
+            backward_jump: register(&format!("{node}.clock_backward_jump")),
+            forward_jump: register(&format!("{node}.clock_forward_jump")),
+            drift: register(&format!("{node}.clock_drift")),
+            freeze: register(&format!("{node}.clock_freeze")),
+            offset: 0,
+            drift_ppm: 0,
+            drift_start: Instant::now(),
+            frozen: None,
+        }
+    }
+
+    /// Returns the offset including drift accumulated so far.
+    fn total_offset(&self) -> i128 {
+        let drifted = self.drift_start.elapsed().as_nanos() as i128 * self.drift_ppm as i128;
+        self.offset + drifted / 1_000_000
+    }
+
+    /// Rolls each anomaly fault, returning the `(fault, details)` of each
+    /// anomaly applied.
+    fn perturb(&mut self) -> Vec<(&'static FaultEntry, serde_json::Value)> {
+        let mut anomalies = vec![];
+        if self.backward_jump.trip() {
+            let jump = random_in(self.backward_jump, BACKWARD_JUMP);
+            self.offset -= jump as i128;
+            anomalies.push((
+                self.backward_jump,
+                serde_json::json!({ "jump_us": -((jump / 1_000) as i64) }),
+            ));
+        }
+        if self.forward_jump.trip() {
+            let jump = random_in(self.forward_jump, FORWARD_JUMP);
+            self.offset += jump as i128;
+            anomalies.push((
+                self.forward_jump,
+                serde_json::json!({ "jump_us": jump / 1_000 }),
+            ));
+        }
+        if self.drift.trip() {
+            self.offset = self.total_offset();
+            self.drift_start = Instant::now();
+            self.drift_ppm = crate::random::range_for(self.drift.name(), 0..=2 * MAX_DRIFT_PPM)
+                as i64
+                - MAX_DRIFT_PPM as i64;
+            anomalies.push((
+                self.drift,
+                serde_json::json!({ "drift_ppm": self.drift_ppm }),
+            ));
+        }
+        if self.frozen.is_none() && self.freeze.trip() {
+            let span = Duration::from_nanos(random_in(self.freeze, FREEZE));
+            self.frozen = Some((self.read(), Instant::now() + span));
+            anomalies.push((
+                self.freeze,
+                serde_json::json!({
+                    "freeze_us": u64::try_from(span.as_micros()).unwrap_or(u64::MAX),
+                }),
+            ));
+        }
+        anomalies
+    }
+
+    /// Returns this node's reading of the wall clock.
+    fn read(&mut self) -> SystemTime {
+        if let Some((reading, until)) = self.frozen {
+            if Instant::now() < until {
+                return reading;
+            }
+            self.frozen = None;
+        }
+        let now = SystemTime::now();
+        let offset = self.total_offset();
+        let magnitude = Duration::from_nanos(offset.unsigned_abs().min(u64::MAX as u128) as u64);
+        if offset >= 0 {
+            now.checked_add(magnitude).unwrap_or(now)
+        } else {
+            now.checked_sub(magnitude).unwrap_or(SystemTime::UNIX_EPOCH)
+        }
+    }
+}
+
+#[cfg(feature = "enabled")]
+static NODE_CLOCKS: Mutex<BTreeMap<String, NodeClock>> = Mutex::new(BTreeMap::new());
+
+/// Returns the wall clock as read by the node labeled `node`.
+///
+/// Each call may inject a clock anomaly on that node via the faults
+/// `{node}.clock_backward_jump`, `{node}.clock_forward_jump`,
+/// `{node}.clock_drift` and `{node}.clock_freeze`. Anomalies persist and
+/// accumulate until [`reset_skew`] is called. Each anomaly is reported as a
+/// `precept_fault` event carrying the node's resulting offset from
+/// [`SystemTime::now`].
+///
+/// When the `enabled` feature is off this is [`SystemTime::now`].
+#[cfg_attr(not(feature = "enabled"), allow(unused_variables))]
+pub fn skewed_now(node: &str) -> SystemTime {
+    #[cfg(not(feature = "enabled"))]
+    return SystemTime::now();
+
+    #[cfg(feature = "enabled")]
+    {
+        let mut clocks = NODE_CLOCKS.lock().unwrap_or_else(PoisonError::into_inner);
+        let clock = clocks
+            .entry(node.to_owned())
+            .or_insert_with(|| NodeClock::new(node));
+        let anomalies = clock.perturb();
+        let offset_us = (clock.total_offset() / 1_000) as i64;
+        let reading = clock.read();
+        drop(clocks);
+
+        for (fault, mut details) in anomalies {
+            details["node"] = node.into();
+            details["offset_us"] = offset_us.into();
+            crate::emit_event!("precept_fault", { "name": fault.name(), "details": details });
+        }
+        reading
+    }
+}
+
+/// Clears every clock anomaly on the node labeled `node`.
+#[cfg_attr(not(feature = "enabled"), allow(unused_variables))]
+pub fn reset_skew(node: &str) {
+    #[cfg(feature = "enabled")]
+    {
+        let mut clocks = NODE_CLOCKS.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(clock) = clocks.get_mut(node) {
+            clock.offset = 0;
+            clock.drift_ppm = 0;
+            clock.frozen = None;
+        }
+    }
+}
+
+#[cfg(all(test, feature = "enabled"))]
+mod tests {
+    use std::time::Duration;
+
+    use super::{reset_skew, skewed_now};
+    use crate::{fault::get_fault_by_name, time::SystemTime};
+
+    fn force(name: &str) -> crate::fault::FaultGuard<'static> {
+        get_fault_by_name(name).unwrap().force_scoped(1)
+    }
+
+    #[test]
+    fn skew_faults() {
+        let _lock = crate::fault::lock_catalog();
+        skewed_now("skew_test");
+        let _quiet = crate::fault::only_enable(&[]);
+
+        reset_skew("skew_test");
+        let _forced = force("skew_test.clock_forward_jump");
+        let skewed = skewed_now("skew_test");
+        assert!(skewed.duration_since(SystemTime::now()).is_ok());
+
+        reset_skew("skew_test");
+        let _forced = force("skew_test.clock_backward_jump");
+        let skewed = skewed_now("skew_test");
+        assert!(skewed < SystemTime::now());
+
+        reset_skew("skew_test");
+        let _forced = force("skew_test.clock_freeze");
+        let frozen = skewed_now("skew_test");
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(skewed_now("skew_test"), frozen);
+    }
+}
@@ -0,0 +1,193 @@
+//! A minimal runtime-agnostic timer used by precept's async fault points.
+//!
+//! Precept can't assume which async runtime the system under test uses, so
+//! async delays are driven by a single background thread which wakes each
+//! pending [`Sleep`] once its deadline has passed.
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    future::Future,
+    pin::Pin,
+    sync::{Condvar, Mutex, OnceLock, PoisonError},
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Returns a future which completes once `duration` has elapsed.
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep { deadline: Instant::now() + duration }
+}
+
+/// Wakes `waker` once `deadline` has passed.
+pub fn wake_at(deadline: Instant, waker: Waker) {
+    let timer = timer();
+    let mut queue = timer.queue.lock().unwrap_or_else(PoisonError::into_inner);
+    queue.push(Timeout { deadline: Reverse(deadline), waker });
+    timer.changed.notify_one();
+}
+
+/// Future returned by [`sleep`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Sleep {
+    deadline: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            Poll::Ready(())
+        } else {
+            wake_at(self.deadline, cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+struct Timeout {
+    deadline: Reverse<Instant>,
+    waker: Waker,
+}
+
+impl PartialEq for Timeout {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for Timeout {}
+
+impl PartialOrd for Timeout {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timeout {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.deadline.cmp(&other.deadline)
+    }
+}
+
+struct Timer {
+    queue: Mutex<BinaryHeap<Timeout>>,
+    changed: Condvar,
+}
+
+fn timer() -> &'static Timer {
+    static TIMER: OnceLock<&'static Timer> = OnceLock::new();
+    TIMER.get_or_init(|| {
+        let timer: &'static Timer = Box::leak(Box::new(Timer {
+            queue: Mutex::new(BinaryHeap::new()),
+            changed: Condvar::new(),
+        }));
+        thread::Builder::new()
+            .name("precept-timer".into())
+            .spawn(move || run(timer))
+            .expect("failed to spawn precept timer thread");
+        timer
+    })
+}
+
+fn run(timer: &'static Timer) -> ! {
+    let mut queue = timer.queue.lock().unwrap_or_else(PoisonError::into_inner);
+    loop {
+        let now = Instant::now();
+        let mut expired = vec![];
+        while queue.peek().is_some_and(|t| t.deadline.0 <= now) {
+            expired.extend(queue.pop());
+        }
+        if !expired.is_empty() {
+            // a waker may call back into the timer, so wake without the lock
+            drop(queue);
+            for timeout in expired {
+                timeout.waker.wake();
+            }
+            queue = timer.queue.lock().unwrap_or_else(PoisonError::into_inner);
+            continue;
+        }
+        queue = match queue.peek() {
+            Some(next) => {
+                let wait = next.deadline.0 - now;
+                timer
+                    .changed
+                    .wait_timeout(queue, wait)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0
+            }
+            None => timer
+                .changed
+                .wait(queue)
+                .unwrap_or_else(PoisonError::into_inner),
+        };
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::{Arc, mpsc},
+        task::{Context, Poll, Wake, Waker},
+        thread::{self, Thread},
+        time::{Duration, Instant},
+    };
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Drives a future to completion on the current thread.
+    pub(crate) fn block_on<F: Future>(fut: F) -> F::Output {
+        let mut fut = pin!(fut);
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(out) => return out,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn sleep_waits_for_deadline() {
+        let start = Instant::now();
+        block_on(super::sleep(Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn wakers_can_reschedule() {
+        /// Schedules `next` when woken, as an executor polling inline might.
+        struct Reschedule(Waker);
+
+        impl Wake for Reschedule {
+            fn wake(self: Arc<Self>) {
+                super::wake_at(Instant::now(), self.0.clone());
+            }
+        }
+
+        struct Notify(mpsc::Sender<()>);
+
+        impl Wake for Notify {
+            fn wake(self: Arc<Self>) {
+                let _ = self.0.send(());
+            }
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let notify = Waker::from(Arc::new(Notify(tx)));
+        super::wake_at(Instant::now(), Arc::new(Reschedule(notify)).into());
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+}